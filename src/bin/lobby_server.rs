@@ -0,0 +1,42 @@
+//! Runs the lobby headless, see `src/lobby_server.rs` for what it does.
+//!
+//! `lobby_server [address]` listens on `0.0.0.0:22030` unless given another address. For testing
+//! on one machine run it without arguments and pick "Connect to lobby" with an empty address in
+//! two games.
+
+use std::{process::ExitCode, sync::atomic::AtomicBool};
+
+use viering_chess_gui::lobby_server::{self, LobbyServer};
+
+const USAGE: &str = "usage:
+    lobby_server [address]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let address = match args.as_slice() {
+        [] => lobby_server::default_address(),
+        [address] if !address.starts_with('-') => address.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut server = match LobbyServer::bind(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Can't listen on {}: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match server.local_addr() {
+        Ok(address) => println!("Lobby listening on {}", address),
+        Err(_) => println!("Lobby listening on {}", address),
+    }
+
+    // runs until the process is killed
+    server.run(&AtomicBool::new(false));
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,60 @@
+//! Plays a scripted opponent against the game, see `src/mock_peer.rs` for the script format.
+//!
+//! `mock_peer serve <address> <script>` waits for the game to join, `mock_peer connect <address>
+//! <script>` joins a game the gui is hosting. `mock_peer replay <address> <capture>` plays the
+//! opponent from a capture recorded by the game, and `mock_peer decode <capture>` prints it.
+
+use std::process::ExitCode;
+
+use viering_chess_gui::mock_peer::{self, MockError, Script};
+
+const USAGE: &str = "usage:
+    mock_peer serve <address> <script>
+    mock_peer connect <address> <script>
+    mock_peer replay <address> <capture>
+    mock_peer decode <capture>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let path = match args.as_slice() {
+        [_, _, path] | [_, path] => path,
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result: Result<(), MockError> = match args.as_slice() {
+        [mode, address, _] if mode == "serve" => {
+            Script::parse(&source).and_then(|script| script.serve(address))
+        }
+        [mode, address, _] if mode == "connect" => {
+            Script::parse(&source).and_then(|script| script.connect(address))
+        }
+        [mode, address, _] if mode == "replay" => Script::replay(&source, address),
+        [mode, _] if mode == "decode" => mock_peer::decode_capture(&source).map(|listing| {
+            print!("{}", listing);
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
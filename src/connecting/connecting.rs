@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::{
+        capture,
+        discovery::{Announcement, Announcer},
+        lobby,
+        networking::{self, ConnectThread},
+    },
+    general::resources::{HostSettings, NetworkHandler, NetworkRole, SoundEffects},
+    main_menu::main_menu::{BUTTON_COLOR, BUTTON_HOVER_COLOR},
+    GameState,
+};
+
+use super::OnConnectingScreen;
+
+/// The connection attempt running in the background while the connecting screen is shown,
+/// removing it cancels the attempt
+#[derive(Resource)]
+pub(crate) struct PendingConnection {
+    connect: ConnectThread,
+    started: f32,
+    /// Tells the local network about the game while the host waits
+    announcer: Option<Announcer>,
+}
+
+#[derive(Component)]
+pub(crate) struct StatusText;
+
+#[derive(Component)]
+pub(crate) struct ElapsedText;
+
+#[derive(Copy, Clone, PartialEq, Component, Debug)]
+pub(crate) enum ConnectingAction {
+    Cancel,
+}
+
+pub(crate) fn connecting_setup(
+    mut commands: Commands,
+    network_handler: Res<NetworkHandler>,
+    host_settings: Res<HostSettings>,
+    time: Res<Time>,
+) {
+    let role = network_handler.role;
+    let address = match role {
+        NetworkRole::Server => host_settings.bind_address.to_string(),
+        NetworkRole::Client if network_handler.spectating => {
+            networking::spectator_address(&network_handler.join_address())
+        }
+        NetworkRole::Client => network_handler.join_address(),
+    };
+
+    // start connecting in the background so the app keeps rendering
+    let settings = host_settings.clone();
+    let name = network_handler.display_name();
+    let lobby_address = network_handler.lobby_address.clone();
+    let lobby_game = network_handler.lobby_game;
+    let password = network_handler.password.clone();
+    let via_lobby = lobby_address.is_some();
+    // nobody else on the network could join a game bound to loopback, and lobby games are
+    // listed by the lobby
+    let announcer = if role == NetworkRole::Server
+        && !host_settings.bind_address.ip().is_loopback()
+        && !via_lobby
+    {
+        Announcer::new(&Announcement {
+            name: name.clone(),
+            port: host_settings.bind_address.port(),
+            time_control: host_settings.time_control,
+            host_color: host_settings.color,
+        })
+        .map_err(|e| println!("Failed to start announcing the game: {}", e))
+        .ok()
+    } else {
+        None
+    };
+
+    let capture = network_handler
+        .record_traffic
+        .then(|| capture::new_capture_path(role));
+    let connect = ConnectThread::spawn(move |cancel| match lobby_address {
+        Some(lobby_address) => lobby::establish(
+            &lobby_address,
+            lobby_game,
+            &name,
+            &settings,
+            capture.as_deref(),
+            cancel,
+        ),
+        None => networking::establish(
+            role,
+            &address,
+            &name,
+            &settings,
+            password.as_deref(),
+            capture.as_deref(),
+            cancel,
+        ),
+    });
+
+    commands.insert_resource(PendingConnection {
+        connect,
+        started: time.elapsed_seconds(),
+        announcer,
+    });
+
+    // general setup
+    commands.spawn((Camera2dBundle::default(), OnConnectingScreen));
+
+    // ui setup
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Vw(100.0),
+                    height: Val::Vh(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnConnectingScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(32.0)),
+                        display: Display::Flex,
+                        align_items: AlignItems::Center,
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(16.0),
+                        ..default()
+                    },
+                    border_radius: BorderRadius::all(Val::Px(12.0)),
+                    background_color: Srgba::rgb_u8(50, 50, 50).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            match (role, via_lobby) {
+                                (NetworkRole::Server, false) => "Waiting for opponent...",
+                                (NetworkRole::Server, true) => "Waiting in the lobby...",
+                                (NetworkRole::Client, false) => "Connecting to host...",
+                                (NetworkRole::Client, true) => "Joining through the lobby...",
+                            },
+                            TextStyle {
+                                font_size: 32.0,
+                                ..default()
+                            },
+                        ),
+                        StatusText,
+                    ));
+
+                    if role == NetworkRole::Server && !via_lobby {
+                        let addresses: Vec<String> =
+                            networking::reachable_addresses(host_settings.bind_address)
+                                .iter()
+                                .map(|address| address.to_string())
+                                .collect();
+
+                        parent.spawn(TextBundle::from_section(
+                            format!("Reachable on {}", addresses.join(", ")),
+                            TextStyle {
+                                font_size: 16.0,
+                                ..default()
+                            },
+                        ));
+                    }
+
+                    parent.spawn((
+                        TextBundle::from_section("0:00", TextStyle { ..default() }),
+                        ElapsedText,
+                    ));
+
+                    // cancel button
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(160.0),
+                                    padding: UiRect::all(Val::Px(8.0)),
+                                    display: Display::Flex,
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                border_radius: BorderRadius::all(Val::Px(6.0)),
+                                background_color: BUTTON_COLOR.into(),
+                                ..default()
+                            },
+                            ConnectingAction::Cancel,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Cancel",
+                                TextStyle { ..default() },
+                            ));
+                        });
+                });
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn connecting_update(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut pending: Option<ResMut<PendingConnection>>,
+    mut network_handler: ResMut<NetworkHandler>,
+    mut elapsed_query: Query<&mut Text, (With<ElapsedText>, Without<StatusText>)>,
+    mut status_query: Query<&mut Text, With<StatusText>>,
+    mut button_query: Query<
+        (&ConnectingAction, &Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+    time: Res<Time>,
+    sound_effects: Res<SoundEffects>,
+) {
+    for (action, interaction, mut background_color) in &mut button_query {
+        match *interaction {
+            Interaction::Pressed => {
+                // click sound
+                commands.spawn(AudioBundle {
+                    source: sound_effects.click.clone(),
+                    ..default()
+                });
+
+                match *action {
+                    ConnectingAction::Cancel => {
+                        game_state.set(GameState::MainMenu);
+                        return;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = BUTTON_HOVER_COLOR.into();
+            }
+            Interaction::None => {
+                *background_color = BUTTON_COLOR.into();
+            }
+        }
+    }
+
+    let Some(pending) = pending.as_mut() else {
+        return;
+    };
+
+    let elapsed = (time.elapsed_seconds() - pending.started) as u32;
+    for mut text in elapsed_query.iter_mut() {
+        text.sections[0].value = format!("{}:{:02}", elapsed / 60, elapsed % 60);
+    }
+
+    if let Some(announcer) = pending.announcer.as_mut() {
+        announcer.update(time.elapsed_seconds());
+    }
+
+    let Some(result) = pending.connect.poll() else {
+        return;
+    };
+
+    match result {
+        Ok(Some(handshake)) => {
+            network_handler.connection = Some(handshake.connection);
+            network_handler.start = Some(handshake.start);
+            network_handler.opponent_name = handshake.opponent_name;
+            network_handler.peer_extensions = handshake.peer_extensions;
+            network_handler.resume_token = handshake.resume_token;
+            game_state.set(GameState::InGame);
+        }
+        Err(e) => {
+            // stay on this screen and show what went wrong, cancel takes us back
+            println!("Network error: {}", e);
+            for mut text in status_query.iter_mut() {
+                text.sections[0].value = e.to_string();
+            }
+        }
+        Ok(None) => {}
+    }
+
+    commands.remove_resource::<PendingConnection>();
+}
+
+/// Stops the background thread if we leave the screen before it finished (i.e. cancel)
+pub(crate) fn connecting_cleanup(mut commands: Commands) {
+    commands.remove_resource::<PendingConnection>();
+}
@@ -0,0 +1,23 @@
+pub mod connecting;
+
+use bevy::prelude::*;
+
+use crate::{despawn_screen, GameState};
+
+#[derive(Component)]
+struct OnConnectingScreen;
+
+pub fn connecting_plugin(app: &mut App) {
+    app.add_systems(OnEnter(GameState::Connecting), connecting::connecting_setup)
+        .add_systems(
+            Update,
+            connecting::connecting_update.run_if(in_state(GameState::Connecting)),
+        )
+        .add_systems(
+            OnExit(GameState::Connecting),
+            (
+                despawn_screen::<OnConnectingScreen>,
+                connecting::connecting_cleanup,
+            ),
+        );
+}
@@ -0,0 +1,197 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::general::resources::NetworkRole;
+
+use super::networking::{Message, NetworkError, Transport};
+
+/// Captures go here, named after the time the connection was made
+pub(crate) const CAPTURE_DIR: &str = "captures";
+
+/// Where a new capture for `role` is written
+pub(crate) fn new_capture_path(role: NetworkRole) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    Path::new(CAPTURE_DIR).join(format!("{}_{}.capture", timestamp, role_name(role)))
+}
+
+fn role_name(role: NetworkRole) -> &'static str {
+    match role {
+        NetworkRole::Server => "server",
+        NetworkRole::Client => "client",
+    }
+}
+
+/// Writes every packet going through `inner` to a file, one line per packet with the time since
+/// the connection was made, the direction and the packet's bytes in hex:
+///
+/// ```text
+/// # role client
+/// 0 -> 85a8...
+/// 153 <- 85a8...
+/// ```
+///
+/// The bytes are the packet encoded again after decoding, which is what we acted on.
+pub(crate) struct Capture {
+    inner: Box<dyn Transport>,
+    file: File,
+    started: Instant,
+}
+
+impl Capture {
+    pub fn new(inner: Box<dyn Transport>, path: &Path, role: NetworkRole) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut file = File::create(path)?;
+        writeln!(file, "# role {}", role_name(role))?;
+
+        Ok(Capture {
+            inner,
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    /// Recording failures are only logged so a full disk doesn't end the game
+    fn record(&mut self, direction: &str, message: &Message) {
+        let millis = self.started.elapsed().as_millis();
+
+        let line = message
+            .clone()
+            .encode()
+            .map(|bytes| format!("{} {} {}", millis, direction, to_hex(&bytes)));
+
+        match line {
+            Ok(line) => {
+                if let Err(e) = writeln!(self.file, "{}", line) {
+                    println!("Failed to write capture: {}", e);
+                }
+            }
+            Err(e) => println!("Failed to capture packet: {}", e),
+        }
+    }
+}
+
+impl Transport for Capture {
+    fn read(&mut self) -> Result<Option<Message>, NetworkError> {
+        let message = self.inner.read()?;
+        if let Some(message) = &message {
+            self.record("<-", message);
+        }
+        Ok(message)
+    }
+
+    fn write(&mut self, message: Message) -> Result<(), NetworkError> {
+        self.record("->", &message);
+        self.inner.write(message)
+    }
+}
+
+pub(crate) struct CapturedPacket {
+    /// Line in the capture file, for error messages
+    pub line: usize,
+    pub millis: u64,
+    /// Sent by the side that recorded the capture, the others came from its opponent
+    pub sent: bool,
+    pub bytes: Vec<u8>,
+}
+
+pub(crate) struct CaptureFile {
+    /// The role of the side that recorded the capture
+    pub role: NetworkRole,
+    pub packets: Vec<CapturedPacket>,
+}
+
+impl CaptureFile {
+    pub fn parse(source: &str) -> Result<CaptureFile, String> {
+        let mut role = None;
+        let mut packets = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: &str| format!("line {}: {}", index + 1, message);
+
+            if let Some(comment) = line.strip_prefix('#') {
+                role = match comment.trim() {
+                    "role server" => Some(NetworkRole::Server),
+                    "role client" => Some(NetworkRole::Client),
+                    _ => role,
+                };
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let [millis, direction, bytes] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(error("expected time, direction and bytes"));
+            };
+
+            packets.push(CapturedPacket {
+                line: index + 1,
+                millis: millis.parse().map_err(|_| error("bad time"))?,
+                sent: match direction {
+                    "->" => true,
+                    "<-" => false,
+                    _ => return Err(error("bad direction")),
+                },
+                bytes: from_hex(bytes).ok_or_else(|| error("bad hex"))?,
+            });
+        }
+
+        Ok(CaptureFile {
+            role: role.ok_or("capture doesn't say which role recorded it")?,
+            packets,
+        })
+    }
+
+    /// One line per packet with the decoded contents
+    pub fn pretty_print(&self) -> String {
+        let mut output = format!("Recorded by the {}\n", role_name(self.role));
+
+        for packet in &self.packets {
+            let contents = match Message::decode(&packet.bytes) {
+                Some(message) => format!("{:?}", message),
+                None => format!("invalid packet {}", to_hex(&packet.bytes)),
+            };
+
+            let _ = writeln!(
+                output,
+                "{:>4}.{:03}s  {}  {}",
+                packet.millis / 1000,
+                packet.millis % 1000,
+                if packet.sent { "sent    " } else { "received" },
+                contents
+            );
+        }
+
+        output
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,391 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use vhultman_chess::{ChessMove, Color as PieceColor, GameState, PieceType, Position};
+
+use super::engine::MovePicker;
+use super::resources::{ClientGameState, Difficulty};
+
+/// Deeper than any search gets in the time it has
+const MAX_DEPTH: u32 = 64;
+/// How many captures are followed past the end of the search, so it doesn't stop in the
+/// middle of a trade
+const QUIESCENCE_DEPTH: u32 = 4;
+const MATE: i32 = 100_000;
+const INFINITY: i32 = 1_000_000;
+
+/// How deep the computer may search and how long it may take for a move
+fn limits(difficulty: Difficulty) -> (u32, Duration) {
+    match difficulty {
+        Difficulty::Beginner => (1, Duration::from_millis(200)),
+        Difficulty::Easy => (2, Duration::from_millis(500)),
+        Difficulty::Medium => (4, Duration::from_secs(1)),
+        Difficulty::Hard => (MAX_DEPTH, Duration::from_secs(3)),
+    }
+}
+
+/// The built-in computer opponent, an alpha-beta search for when there's no engine around
+pub(crate) struct Computer {
+    difficulty: Difficulty,
+}
+
+impl Computer {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Computer { difficulty }
+    }
+}
+
+impl MovePicker for Computer {
+    fn new_game(&mut self, _cancel: &AtomicBool) -> Result<bool, String> {
+        Ok(true)
+    }
+
+    fn best_move(
+        &mut self,
+        game_state: &ClientGameState,
+        cancel: &AtomicBool,
+    ) -> Result<Option<ChessMove>, String> {
+        let (max_depth, think_time) = limits(self.difficulty);
+        let best = Search::new(Instant::now() + think_time, cancel)
+            .best_move(&game_state.board_state, max_depth);
+
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        best.map(Some).ok_or("computer has no move".to_string())
+    }
+}
+
+struct Search<'a> {
+    deadline: Instant,
+    cancel: &'a AtomicBool,
+    nodes: u64,
+    /// Set once the deadline passed or we were cancelled, scores found after that are junk
+    stopped: bool,
+}
+
+impl<'a> Search<'a> {
+    fn new(deadline: Instant, cancel: &'a AtomicBool) -> Self {
+        Search {
+            deadline,
+            cancel,
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    /// Searches one ply deeper at a time until `max_depth` or the deadline. The best move so
+    /// far is searched first, so a move from a depth that was cut short only replaces it if
+    /// it scored better.
+    fn best_move(&mut self, position: &Position, max_depth: u32) -> Option<ChessMove> {
+        let mut moves = ordered_moves(position);
+        if moves.is_empty() {
+            return None;
+        }
+
+        for depth in 1..=max_depth {
+            let mut alpha = -INFINITY;
+            let mut best = 0;
+
+            for (i, &m) in moves.iter().enumerate() {
+                let mut next = position.clone();
+                next.make_move(m);
+                let score = -self.negamax(&next, depth - 1, 1, -INFINITY, -alpha);
+                if self.stopped {
+                    break;
+                }
+
+                if score > alpha {
+                    alpha = score;
+                    best = i;
+                }
+            }
+
+            moves[..=best].rotate_right(1);
+
+            // nothing beats a mate
+            if self.stopped || alpha >= MATE - MAX_DEPTH as i32 {
+                break;
+            }
+        }
+
+        Some(moves[0])
+    }
+
+    fn negamax(
+        &mut self,
+        position: &Position,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let moves = ordered_moves(position);
+        if moves.is_empty() {
+            // a later mate is a worse one
+            return match position.check_game_state() {
+                GameState::Checkmate => -MATE + ply,
+                _ => 0,
+            };
+        }
+
+        if depth == 0 {
+            return self.quiesce(position, moves, alpha, beta, QUIESCENCE_DEPTH);
+        }
+
+        for m in moves {
+            let mut next = position.clone();
+            next.make_move(m);
+            let score = -self.negamax(&next, depth - 1, ply + 1, -beta, -alpha);
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    /// Only looks at captures, the side to move can also stop capturing and keep the
+    /// position as it is
+    fn quiesce(
+        &mut self,
+        position: &Position,
+        moves: Vec<ChessMove>,
+        mut alpha: i32,
+        beta: i32,
+        depth: u32,
+    ) -> i32 {
+        let stand_pat = evaluate(position);
+        if stand_pat >= beta {
+            return beta;
+        }
+        alpha = alpha.max(stand_pat);
+
+        if depth == 0 {
+            return alpha;
+        }
+
+        for m in moves.into_iter().filter(|m| is_capture(position, *m)) {
+            if self.out_of_time() {
+                return 0;
+            }
+
+            let mut next = position.clone();
+            next.make_move(m);
+            let next_moves = ordered_moves(&next);
+            let score = -self.quiesce(&next, next_moves, -beta, -alpha, depth - 1);
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    /// Looks at the clock every so often, it's too slow to ask every node
+    fn out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes % 1024 == 0
+            && (Instant::now() >= self.deadline || self.cancel.load(Ordering::Relaxed))
+        {
+            self.stopped = true;
+        }
+
+        self.stopped
+    }
+}
+
+/// The legal moves of the side to move with the most valuable captures first. Pawns always
+/// promote to a queen.
+fn ordered_moves(position: &Position) -> Vec<ChessMove> {
+    let side = position.current_side();
+    let mut moves = Vec::new();
+
+    for square in 0..64 {
+        if !position
+            .piece_on(square)
+            .is_some_and(|piece| piece.color == side)
+        {
+            continue;
+        }
+
+        for mut m in position.moves_for_square(square).iter().copied() {
+            if m.is_promotion() {
+                m.set_promotion_piece(PieceType::Queen);
+            }
+            moves.push(m);
+        }
+    }
+
+    // most valuable victim, then least valuable attacker
+    moves.sort_by_key(
+        |m| match (position.piece_on(m.to()), position.piece_on(m.from())) {
+            (Some(victim), Some(attacker)) => piece_value(attacker.t) - 10 * piece_value(victim.t),
+            _ => 0,
+        },
+    );
+
+    moves
+}
+
+fn is_capture(position: &Position, m: ChessMove) -> bool {
+    position.piece_on(m.to()).is_some()
+}
+
+fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 0,
+    }
+}
+
+/// Material and where the pieces stand, from the point of view of the side to move
+fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+
+    for square in 0..64 {
+        let Some(piece) = position.piece_on(square) else {
+            continue;
+        };
+
+        // the tables are from white's side with a8 first, like board ids
+        let (sign, index) = match piece.color {
+            PieceColor::White => (1, square),
+            PieceColor::Black => (-1, square ^ 56),
+        };
+        score += sign * (piece_value(piece.t) + square_table(piece.t)[index as usize]);
+    }
+
+    match position.current_side() {
+        PieceColor::White => score,
+        PieceColor::Black => -score,
+    }
+}
+
+fn square_table(piece_type: PieceType) -> &'static [i32; 64] {
+    match piece_type {
+        PieceType::Pawn => &PAWN_TABLE,
+        PieceType::Knight => &KNIGHT_TABLE,
+        PieceType::Bishop => &BISHOP_TABLE,
+        PieceType::Rook => &ROOK_TABLE,
+        PieceType::Queen => &QUEEN_TABLE,
+        PieceType::King => &KING_TABLE,
+    }
+}
+
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+/// Keeps the king tucked away behind its pawns, which is wrong in the endgame but simple
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::move_name;
+
+    fn best_move(fen: &str, depth: u32) -> String {
+        let cancel = AtomicBool::new(false);
+        let position = Position::from_fen(fen).unwrap();
+        let m = Search::new(Instant::now() + Duration::from_secs(60), &cancel)
+            .best_move(&position, depth)
+            .unwrap();
+
+        move_name(m)
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        assert_eq!(best_move("4k3/8/8/3q4/8/8/8/3QK3 w - - 0 1", 2), "d1d5");
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        assert_eq!(best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3), "a1a8");
+    }
+
+    #[test]
+    fn starting_position_is_even() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        assert_eq!(evaluate(&position), 0);
+    }
+}
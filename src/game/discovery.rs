@@ -0,0 +1,170 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::general::resources::HostColor;
+
+use super::resources::TimeControl;
+
+/// Hosts announce themselves on this port, next to the default game port
+pub(crate) const DISCOVERY_PORT: u16 = 22021;
+
+/// Seconds between announcements
+pub(crate) const ANNOUNCE_INTERVAL: f32 = 1.0;
+
+/// Games that haven't been announced for this many seconds are dropped from the list
+pub(crate) const ANNOUNCE_TIMEOUT: f32 = 3.5;
+
+/// First field of every announcement so unrelated broadcasts on the port are ignored
+const MAGIC: &str = "viering-chess/1";
+
+/// What a host broadcasts about the game it's waiting to start. The host's ip is taken from
+/// the sender address of the datagram, so only the port is included.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Announcement {
+    pub name: String,
+    pub port: u16,
+    pub time_control: Option<TimeControl>,
+    /// The color the host plays, the joining player gets the other one
+    pub host_color: HostColor,
+}
+
+impl Announcement {
+    /// Tab separated text, the name goes last so it may contain anything but tabs and newlines
+    pub fn encode(&self) -> Vec<u8> {
+        let time_control = match self.time_control {
+            Some(time_control) => format!("{}+{}", time_control.time, time_control.inc),
+            None => "-".to_string(),
+        };
+        let color = match self.host_color {
+            HostColor::White => "w",
+            HostColor::Black => "b",
+            HostColor::Random => "r",
+        };
+        let name = self.name.replace(['\t', '\n'], " ");
+
+        format!("{MAGIC}\t{}\t{time_control}\t{color}\t{name}", self.port).into_bytes()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(buf).ok()?;
+        let mut fields = text.splitn(5, '\t');
+
+        if fields.next()? != MAGIC {
+            return None;
+        }
+
+        let port = fields.next()?.parse().ok()?;
+        let time_control = match fields.next()? {
+            "-" => None,
+            time_control => {
+                let (time, inc) = time_control.split_once('+')?;
+                Some(TimeControl {
+                    time: time.parse().ok()?,
+                    inc: inc.parse().ok()?,
+                })
+            }
+        };
+        let host_color = match fields.next()? {
+            "w" => HostColor::White,
+            "b" => HostColor::Black,
+            "r" => HostColor::Random,
+            _ => return None,
+        };
+        let name = fields.next()?.to_string();
+
+        Some(Announcement {
+            name,
+            port,
+            time_control,
+            host_color,
+        })
+    }
+}
+
+/// Broadcasts an announcement on the local network, used by the host while it waits
+pub(crate) struct Announcer {
+    socket: UdpSocket,
+    announcement: Vec<u8>,
+    last_sent: Option<f32>,
+}
+
+impl Announcer {
+    pub fn new(announcement: &Announcement) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Announcer {
+            socket,
+            announcement: announcement.encode(),
+            last_sent: None,
+        })
+    }
+
+    /// Sends the announcement if `ANNOUNCE_INTERVAL` has passed since the last one. Failures
+    /// are only logged, discovery is a convenience and joining by address still works.
+    pub fn update(&mut self, now: f32) {
+        if self
+            .last_sent
+            .is_some_and(|last_sent| now - last_sent < ANNOUNCE_INTERVAL)
+        {
+            return;
+        }
+        self.last_sent = Some(now);
+
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), DISCOVERY_PORT);
+        if let Err(e) = self.socket.send_to(&self.announcement, target) {
+            println!("Failed to announce game: {}", e);
+        }
+    }
+}
+
+/// Receives announcements, used by the main menu
+pub(crate) struct Listener {
+    socket: UdpSocket,
+}
+
+impl Listener {
+    /// Binds the discovery port with address reuse so several menus on one machine can listen
+    pub fn new() -> std::io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DISCOVERY_PORT).into())?;
+
+        Ok(Listener {
+            socket: socket.into(),
+        })
+    }
+
+    /// Every announcement that arrived since the last call, with the address of the game
+    pub fn receive(&self) -> Vec<(SocketAddr, Announcement)> {
+        let mut announcements = Vec::new();
+        let mut buf = [0; 512];
+
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, sender)) => {
+                    if let Some(announcement) = Announcement::decode(&buf[..len]) {
+                        let address = SocketAddr::new(sender.ip(), announcement.port);
+                        announcements.push((address, announcement));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Failed to receive announcement: {}", e);
+                    break;
+                }
+            }
+        }
+
+        announcements
+    }
+}
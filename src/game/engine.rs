@@ -0,0 +1,405 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::prelude::Resource;
+use vhultman_chess::{ChessMove, PieceType};
+
+use crate::general::resources::{EngineSettings, NetworkRole};
+
+use super::networking::{client_start, Message, Transport};
+use super::resources::{ClientGameState, NetworkState};
+use super::systems::board::receive_packets;
+use super::utils::{move_name, square_name_to_board_id};
+
+/// How often the engine's side looks for news from the game while it isn't thinking
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Decides the engine's moves, a UCI engine or the built-in computer
+pub(crate) trait MovePicker: Send + 'static {
+    /// Gets ready for a game, `false` if we were cancelled first
+    fn new_game(&mut self, cancel: &AtomicBool) -> Result<bool, String>;
+
+    /// The move to play in the game's current position, `None` if we were cancelled first
+    fn best_move(
+        &mut self,
+        game_state: &ClientGameState,
+        cancel: &AtomicBool,
+    ) -> Result<Option<ChessMove>, String>;
+}
+
+/// An engine playing the other side of a game. It runs on a thread of its own and plays over
+/// a loopback like a remote client would, so its moves arrive at the game the same way network
+/// moves do. Dropping this stops the engine.
+#[derive(Resource)]
+pub(crate) struct EngineOpponent {
+    cancel: Arc<AtomicBool>,
+}
+
+impl EngineOpponent {
+    /// Lets `picker` play the game set up by `start`, which is sent from the host's side like
+    /// in a handshake. Anything going wrong closes `connection`.
+    pub fn start(
+        picker: impl MovePicker,
+        name: &str,
+        start: &chess_networking::Start,
+        connection: Box<dyn Transport>,
+    ) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let name = name.to_string();
+        let start = start.clone();
+        let thread_cancel = cancel.clone();
+        std::thread::spawn(move || {
+            let mut connection = connection;
+            if let Err(e) = play(picker, &name, start, connection.as_mut(), &thread_cancel) {
+                println!("Engine stopped: {}", e);
+            }
+        });
+
+        EngineOpponent { cancel }
+    }
+
+    /// Starts the UCI engine at `settings.path`, fails only if it can't be started
+    pub fn start_uci(
+        settings: &EngineSettings,
+        start: &chess_networking::Start,
+        connection: Box<dyn Transport>,
+    ) -> io::Result<Self> {
+        let uci = Uci::spawn(settings)?;
+        Ok(Self::start(
+            uci,
+            &engine_name(&settings.path),
+            start,
+            connection,
+        ))
+    }
+}
+
+impl Drop for EngineOpponent {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The name shown for the engine, its file name without the extension
+pub(crate) fn engine_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map_or("engine".to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        })
+}
+
+/// An engine process spoken to over its stdin and stdout
+struct Uci {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines from the engine, read on a thread of their own so waiting can be cancelled
+    lines: Receiver<String>,
+    skill: u8,
+    think_time: Duration,
+    /// Set once the engine answered `uci`
+    initialized: bool,
+}
+
+impl Uci {
+    fn spawn(settings: &EngineSettings) -> io::Result<Self> {
+        let mut child = Command::new(&settings.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(io::Error::other("engine has no stdin or stdout"));
+        };
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Uci {
+            child,
+            stdin,
+            lines,
+            skill: settings.skill,
+            think_time: settings.think_time,
+            initialized: false,
+        })
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Waits for a line starting with `prefix`, `None` if we were cancelled first
+    fn wait_for(&mut self, prefix: &str, cancel: &AtomicBool) -> io::Result<Option<String>> {
+        loop {
+            match self.lines.recv_timeout(Duration::from_millis(50)) {
+                Ok(line) if line.starts_with(prefix) => return Ok(Some(line)),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) if cancel.load(Ordering::Relaxed) => {
+                    return Ok(None)
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine quit"))
+                }
+            }
+        }
+    }
+
+    /// The engine's move in `position`, in coordinate notation
+    fn go(&mut self, position: &str, cancel: &AtomicBool) -> io::Result<Option<String>> {
+        self.send(position)?;
+        self.send(&format!(
+            "go movetime {}",
+            self.think_time.as_millis().max(1)
+        ))?;
+
+        let Some(line) = self.wait_for("bestmove", cancel)? else {
+            return Ok(None);
+        };
+        match line.split_whitespace().nth(1) {
+            Some(best) if best != "(none)" => Ok(Some(best.to_string())),
+            _ => Err(io::Error::other("engine has no move")),
+        }
+    }
+}
+
+impl MovePicker for Uci {
+    fn new_game(&mut self, cancel: &AtomicBool) -> Result<bool, String> {
+        // the first game switches the engine to UCI and sets it up
+        if !self.initialized {
+            self.send("uci").map_err(|e| e.to_string())?;
+            if self
+                .wait_for("uciok", cancel)
+                .map_err(|e| e.to_string())?
+                .is_none()
+            {
+                return Ok(false);
+            }
+
+            // engines without the option ignore it
+            self.send(&format!("setoption name Skill Level value {}", self.skill))
+                .map_err(|e| e.to_string())?;
+            self.initialized = true;
+        }
+
+        self.send("ucinewgame").map_err(|e| e.to_string())?;
+        self.send("isready").map_err(|e| e.to_string())?;
+        Ok(self
+            .wait_for("readyok", cancel)
+            .map_err(|e| e.to_string())?
+            .is_some())
+    }
+
+    fn best_move(
+        &mut self,
+        game_state: &ClientGameState,
+        cancel: &AtomicBool,
+    ) -> Result<Option<ChessMove>, String> {
+        let Some(best) = self
+            .go(&uci_position(game_state), cancel)
+            .map_err(|e| e.to_string())?
+        else {
+            return Ok(None);
+        };
+
+        let (from, to, promotion) =
+            parse_uci_move(&best).ok_or(format!("engine sent a bad move {}", best))?;
+        let mut m = game_state
+            .board_state
+            .get_move(from, to)
+            .ok_or(format!("engine played the illegal move {}", best))?;
+        if let Some(promotion) = promotion {
+            m.set_promotion_piece(promotion);
+        }
+
+        Ok(Some(m))
+    }
+}
+
+impl Drop for Uci {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The game as a UCI `position` command. The moves are sent along with the position they were
+/// played from instead of only the current `board_state`, so the engine knows about repetitions.
+fn uci_position(game_state: &ClientGameState) -> String {
+    let mut command = match &game_state.start.fen {
+        Some(fen) => format!("position fen {}", fen),
+        None => "position startpos".to_string(),
+    };
+
+    if !game_state.history.is_empty() {
+        command.push_str(" moves");
+        for m in &game_state.history {
+            command.push(' ');
+            command.push_str(&move_name(*m));
+        }
+    }
+
+    command
+}
+
+/// Board ids and promotion piece of a move in coordinate notation like "e7e8q"
+fn parse_uci_move(name: &str) -> Option<(u32, u32, Option<PieceType>)> {
+    let from = square_name_to_board_id(name.get(0..2)?)?;
+    let to = square_name_to_board_id(name.get(2..4)?)?;
+    let promotion = match name.get(4..) {
+        None | Some("") => None,
+        Some("q") => Some(PieceType::Queen),
+        Some("r") => Some(PieceType::Rook),
+        Some("b") => Some(PieceType::Bishop),
+        Some("n") => Some(PieceType::Knight),
+        Some(_) => return None,
+    };
+
+    Some((from, to, promotion))
+}
+
+/// Plays the client's side of the game for the engine until it's cancelled or the connection
+/// closes. It always wants a rematch and never takes a draw.
+fn play(
+    mut picker: impl MovePicker,
+    name: &str,
+    start: chess_networking::Start,
+    connection: &mut dyn Transport,
+    cancel: &AtomicBool,
+) -> Result<(), String> {
+    if !picker.new_game(cancel)? {
+        return Ok(());
+    }
+
+    let mut game_state =
+        ClientGameState::from_start(&start, NetworkRole::Client).map_err(|e| e.to_string())?;
+    let mut asked_for_rematch = false;
+
+    while !cancel.load(Ordering::Relaxed) {
+        receive_packets(&mut game_state, connection).map_err(|e| e.to_string())?;
+
+        if game_state.is_game_over() {
+            if let Some(start) = game_state.rematch_start.take() {
+                let rematch = ClientGameState::from_start(&start, NetworkRole::Client)
+                    .map_err(|e| e.to_string())?;
+                if !picker.new_game(cancel)? {
+                    break;
+                }
+                game_state = rematch;
+                asked_for_rematch = false;
+            } else if !asked_for_rematch {
+                // the engine only plays, it has none of our extensions
+                connection
+                    .write(Message::Start(client_start(name), Vec::new()))
+                    .map_err(|e| e.to_string())?;
+                asked_for_rematch = true;
+            }
+        }
+
+        if game_state.incoming_draw_offer {
+            game_state.incoming_draw_offer = false;
+            game_state.network_state = NetworkState::Normal;
+            connection
+                .write(Message::Ack(chess_networking::Ack {
+                    ok: true,
+                    end_state: None,
+                }))
+                .map_err(|e| e.to_string())?;
+        }
+
+        if game_state.network_state != NetworkState::Normal || game_state.is_game_over() {
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let Some(m) = picker.best_move(&game_state, cancel)? else {
+            break;
+        };
+
+        let packet = game_state.play_own_move(m);
+        connection
+            .write(Message::Move(packet))
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(fen: Option<&str>) -> chess_networking::Start {
+        chess_networking::Start {
+            is_white: true,
+            name: None,
+            fen: fen.map(|fen| fen.to_string()),
+            time: None,
+            inc: None,
+        }
+    }
+
+    #[test]
+    fn position_lists_the_moves_from_the_start() {
+        let mut game_state =
+            ClientGameState::from_start(&start(None), NetworkRole::Server).unwrap();
+        assert_eq!(uci_position(&game_state), "position startpos");
+
+        for (from, to) in [("e2", "e4"), ("e7", "e5")] {
+            let m = game_state
+                .board_state
+                .get_move(
+                    square_name_to_board_id(from).unwrap(),
+                    square_name_to_board_id(to).unwrap(),
+                )
+                .unwrap();
+            game_state.board_state.make_move(m);
+            game_state.history.push(m);
+        }
+        assert_eq!(
+            uci_position(&game_state),
+            "position startpos moves e2e4 e7e5"
+        );
+
+        let fen = "7k/8/8/8/8/8/8/K6R w - - 0 1";
+        let game_state =
+            ClientGameState::from_start(&start(Some(fen)), NetworkRole::Server).unwrap();
+        assert_eq!(uci_position(&game_state), format!("position fen {}", fen));
+    }
+
+    #[test]
+    fn parses_coordinate_moves() {
+        assert_eq!(parse_uci_move("e2e4"), Some((52, 36, None)));
+        assert_eq!(
+            parse_uci_move("a7a8q"),
+            Some((8, 0, Some(PieceType::Queen)))
+        );
+        assert_eq!(parse_uci_move("e2"), None);
+        assert_eq!(parse_uci_move("e2e9"), None);
+        assert_eq!(parse_uci_move("a7a8k"), None);
+    }
+}
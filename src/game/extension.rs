@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use super::lobby::LobbyMessage;
+use super::networking::NetworkError;
+
+/// Sent with our capabilities, bumped when an extension changes in a way its name doesn't tell
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+/// Chat lines, only sent to peers that support it
+pub(crate) const CHAT: &str = "chat";
+
+/// Asking for and answering takebacks
+pub(crate) const TAKEBACK: &str = "takeback";
+
+/// Coming back to an interrupted game with the token the host handed out
+pub(crate) const RESUME: &str = "resume";
+
+/// The extensions we list in our `Start` packets and capabilities
+pub(crate) fn supported() -> Vec<String> {
+    vec![CHAT.to_string(), TAKEBACK.to_string(), RESUME.to_string()]
+}
+
+/// Messages of our own that travel next to the chess_networking packets. They are MessagePack
+/// like the packets, a map with the extension's name as the only key, so a frame can't be
+/// mistaken for a `Start`, `Move` or `Ack` which all have required fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Extension {
+    Lobby(LobbyMessage),
+    /// A line typed into the chat
+    Chat(String),
+    /// Asks to take back the last `plies` half moves of the game as it was after `moves` of them
+    Takeback {
+        plies: u32,
+        moves: u32,
+    },
+    /// The answer to a `Takeback`, both sides roll the game back if it's accepted
+    TakebackAnswer(bool),
+    /// The game password, sent by the client before its `Start` when it has one
+    Password(String),
+    /// The host's answer to a wrong or missing password, it hangs up after this. Braced so it's
+    /// a map like the others instead of a bare string.
+    PasswordRejected {},
+    /// Sent by the host right after its `Start`, what the client presents when it comes back
+    ResumeToken(String),
+    /// The token of the game a returning client wants to resume, sent before its `Start`
+    Resume(String),
+    /// Sent by both players right after the `Start` handshake, see `PeerCapabilities`
+    Capabilities {
+        version: u32,
+        features: Vec<String>,
+    },
+    /// An extension of a newer client, decoded only as far as its name so it can be ignored
+    #[serde(skip)]
+    Unknown(String),
+}
+
+impl Extension {
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        rmp_serde::from_slice(buf).ok().or_else(|| {
+            let mut map: HashMap<String, IgnoredAny> = rmp_serde::from_slice(buf).ok()?;
+            if map.len() != 1 {
+                return None;
+            }
+            map.drain().next().map(|(name, _)| Extension::Unknown(name))
+        })
+    }
+
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        rmp_serde::to_vec_named(self).ok()
+    }
+}
+
+/// The extra field of our `Start` packets. Other clients skip it like any field they don't
+/// know, so they see a plain `Start` and never get sent anything they can't decode.
+#[derive(Serialize, Deserialize)]
+struct StartExtensions {
+    #[serde(default)]
+    extensions: Vec<String>,
+}
+
+/// Adds the extensions field to an encoded `Start`, which is a map of a few fields. Fails if
+/// the packet doesn't start with a map header.
+pub(crate) fn add_to_start(
+    packet: &mut Vec<u8>,
+    extensions: Vec<String>,
+) -> Result<(), NetworkError> {
+    if extensions.is_empty() {
+        return Ok(());
+    }
+
+    let field = rmp_serde::to_vec_named(&StartExtensions { extensions })
+        .map_err(|_| NetworkError::Encode)?;
+
+    // fixmap, map16 or map32, the entry count and how long the header is
+    let (len, header_len) = match packet.as_slice() {
+        [marker @ 0x80..=0x8f, ..] => (u32::from(marker & 0x0f), 1),
+        [0xde, a, b, ..] => (u32::from(u16::from_be_bytes([*a, *b])), 3),
+        [0xdf, a, b, c, d, ..] => (u32::from_be_bytes([*a, *b, *c, *d]), 5),
+        _ => return Err(NetworkError::Encode),
+    };
+    let len = len.checked_add(1).ok_or(NetworkError::Encode)?;
+
+    // one more entry in the map, which may need a bigger header
+    packet.splice(..header_len, map_header(len));
+    // then the field without the header of its own map
+    packet.extend_from_slice(&field[1..]);
+    Ok(())
+}
+
+/// The smallest msgpack map header for `len` entries
+fn map_header(len: u32) -> Vec<u8> {
+    match len {
+        0..=15 => vec![0x80 | len as u8],
+        16..=0xffff => [&[0xde][..], &(len as u16).to_be_bytes()].concat(),
+        _ => [&[0xdf][..], &len.to_be_bytes()].concat(),
+    }
+}
+
+/// The extensions listed in an encoded `Start`, none for other clients
+pub(crate) fn from_start(packet: &[u8]) -> Vec<String> {
+    rmp_serde::from_slice::<StartExtensions>(packet)
+        .map(|start| start.extensions)
+        .unwrap_or_default()
+}
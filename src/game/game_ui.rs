@@ -1,10 +1,28 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use bevy::prelude::*;
-use chess_networking::PromotionPiece;
-use vhultman_chess::{Color as PieceColor, GameState, PieceType};
+use bevy_simple_text_input::{TextInputBundle, TextInputInactive, TextInputSubmitEvent};
+use vhultman_chess::{Color as PieceColor, PieceType};
 
-use crate::{game::ClientGameState, general::resources::NetworkHandler};
+use crate::{
+    game::{
+        color_name,
+        engine::EngineOpponent,
+        extension::{self, Extension},
+        networking::{client_start, Message},
+        other_color, position_to_fen,
+        record::SAVE_DIR,
+        resources::{ChatLine, PeerCapabilities},
+        ClientGameState, EndReason, GameResult, HotSeat, SelfPlay,
+    },
+    general::resources::{HostSettings, NetworkHandler, NetworkRole},
+    GameState as AppState,
+};
 
-use super::NetworkState;
+use super::{
+    systems::reconnect::{start_reconnect, Reconnection},
+    NetworkState, OnGameScreen,
+};
 
 #[derive(Component)]
 pub struct TurnText;
@@ -12,6 +30,15 @@ pub struct TurnText;
 #[derive(Component)]
 pub struct GameStateText;
 
+#[derive(Component)]
+pub struct ClockText(pub PieceColor);
+
+#[derive(Component)]
+pub struct OpponentText;
+
+#[derive(Component)]
+pub struct MoveListText;
+
 #[derive(Component)]
 pub struct GameStatePopupWindow;
 
@@ -21,6 +48,107 @@ pub struct PromotionPopupWindow;
 #[derive(Component)]
 pub struct WaitingForOpponentWindow;
 
+#[derive(Component)]
+pub struct ConnectionLostWindow;
+
+#[derive(Component)]
+pub struct ConnectionLostText;
+
+#[derive(Component)]
+pub struct SavedPositionText;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum ConnectionLostAction {
+    Reconnect,
+    ClaimWin,
+    SavePosition,
+    MainMenu,
+}
+
+#[derive(Component)]
+pub struct OfferDrawText;
+
+#[derive(Component)]
+pub struct DrawOfferWindow;
+
+#[derive(Component)]
+pub struct TakebackText;
+
+#[derive(Component)]
+pub struct TakebackWindow;
+
+#[derive(Component)]
+pub struct TakebackRequestText;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum GameAction {
+    Resign,
+    OfferDraw,
+    Takeback,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum TakebackAction {
+    Accept,
+    Decline,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum DrawOfferAction {
+    Accept,
+    Decline,
+}
+
+#[derive(Component)]
+pub struct RematchText;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum GameOverAction {
+    Rematch,
+    MainMenu,
+}
+
+#[derive(Component)]
+pub struct NotRespondingWindow;
+
+#[derive(Component)]
+pub struct NotRespondingText;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum NotRespondingAction {
+    ClaimWin,
+    KeepWaiting,
+}
+
+/// The chat panel, hidden when the opponent's client doesn't chat
+#[derive(Component)]
+pub struct ChatWindow;
+
+/// The part of the chat panel that collapses
+#[derive(Component)]
+pub struct ChatBody;
+
+#[derive(Component)]
+pub struct ChatLogText;
+
+#[derive(Component)]
+pub struct ChatToggleText;
+
+#[derive(Component)]
+pub struct ChatInput;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub enum ChatAction {
+    Toggle,
+}
+
+/// Whether the chat panel is open and how many lines were there when it was last looked at
+#[derive(Resource, Default)]
+pub struct ChatView {
+    open: bool,
+    seen: usize,
+}
+
 #[derive(Component, Clone, Copy, Debug)]
 pub enum PromotionMenuAction {
     Knight,
@@ -29,25 +157,33 @@ pub enum PromotionMenuAction {
     Queen,
 }
 
-pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    network_handler: Res<NetworkHandler>,
+    hot_seat: Option<Res<HotSeat>>,
+) {
     commands
-        .spawn(NodeBundle {
-            style: Style {
-                padding: UiRect::all(Val::Px(12.0)),
-                margin: UiRect {
-                    left: Val::Px(12.0),
-                    top: Val::Px(12.0),
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    padding: UiRect::all(Val::Px(12.0)),
+                    margin: UiRect {
+                        left: Val::Px(12.0),
+                        top: Val::Px(12.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    display: Display::Flex,
+                    row_gap: Val::Px(6.0),
                     ..default()
                 },
-                flex_direction: FlexDirection::Column,
-                display: Display::Flex,
-                row_gap: Val::Px(6.0),
+                border_radius: BorderRadius::all(Val::Px(6.0)),
+                background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
                 ..default()
             },
-            border_radius: BorderRadius::all(Val::Px(6.0)),
-            background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
-            ..default()
-        })
+            OnGameScreen,
+        ))
         .with_children(|parent| {
             parent.spawn((
                 TextBundle::from_section(
@@ -60,6 +196,96 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 ),
                 TurnText,
             ));
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::srgb_u8(0, 0, 0),
+                        ..default()
+                    },
+                ),
+                OpponentText,
+            ));
+
+            for color in [PieceColor::White, PieceColor::Black] {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 20.0,
+                            color: Color::srgb_u8(0, 0, 0),
+                            ..default()
+                        },
+                    ),
+                    ClockText(color),
+                ));
+            }
+
+            // spectators can't act on the game, they follow its moves instead
+            if network_handler.spectating {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 16.0,
+                            color: Color::srgb_u8(0, 0, 0),
+                            ..default()
+                        },
+                    ),
+                    MoveListText,
+                ));
+            } else {
+                parent
+                    .spawn((dialog_button_bundle(), GameAction::Resign))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "Resign",
+                            TextStyle {
+                                font_size: 20.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ));
+                    });
+
+                // players at the same window can just agree
+                if hot_seat.is_none() {
+                    parent
+                        .spawn((dialog_button_bundle(), GameAction::OfferDraw))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "Offer draw",
+                                    TextStyle {
+                                        font_size: 20.0,
+                                        color: Color::srgb_u8(0, 0, 0),
+                                        ..default()
+                                    },
+                                ),
+                                OfferDrawText,
+                            ));
+                        });
+                }
+
+                // only there when the opponent's client does takebacks
+                parent
+                    .spawn((dialog_button_bundle(), GameAction::Takeback))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                "Take back",
+                                TextStyle {
+                                    font_size: 20.0,
+                                    color: Color::srgb_u8(0, 0, 0),
+                                    ..default()
+                                },
+                            ),
+                            TakebackText,
+                        ));
+                    });
+            }
         });
 
     // game state window
@@ -78,6 +304,7 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 ..default()
             },
             GameStatePopupWindow,
+            OnGameScreen,
         ))
         .with_children(|parent| {
             parent
@@ -105,6 +332,48 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                         ),
                         GameStateText,
                     ));
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        RematchText,
+                    ));
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                display: Display::Flex,
+                                column_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, label) in [
+                                (GameOverAction::Rematch, "Rematch"),
+                                (GameOverAction::MainMenu, "Back to menu"),
+                            ] {
+                                parent
+                                    .spawn((dialog_button_bundle(), action))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            label,
+                                            TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::srgb_u8(0, 0, 0),
+                                                ..default()
+                                            },
+                                        ));
+                                    });
+                            }
+                        });
                 });
         });
 
@@ -123,6 +392,7 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 ..default()
             },
             WaitingForOpponentWindow,
+            OnGameScreen,
         ))
         .with_children(|parent| {
             parent
@@ -150,22 +420,113 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 });
         });
 
-    // promotion window
+    // connection lost window
     commands
         .spawn((
             NodeBundle {
                 style: Style {
                     width: Val::Vw(100.0),
                     height: Val::Vh(100.0),
-                    flex_direction: FlexDirection::Column,
-                    align_items: AlignItems::Center,
+                    display: Display::None,
                     justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            ConnectionLostWindow,
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(12.0)),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        display: Display::Flex,
+                        row_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                    border_radius: BorderRadius::all(Val::Px(6.0)),
+                    background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Connection lost",
+                            TextStyle {
+                                font_size: 24.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        ConnectionLostText,
+                    ));
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        SavedPositionText,
+                    ));
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                display: Display::Flex,
+                                column_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, label) in [
+                                (ConnectionLostAction::Reconnect, "Reconnect"),
+                                (ConnectionLostAction::ClaimWin, "Claim win"),
+                                (ConnectionLostAction::SavePosition, "Save position"),
+                                (ConnectionLostAction::MainMenu, "Main menu"),
+                            ] {
+                                parent
+                                    .spawn((dialog_button_bundle(), action))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            label,
+                                            TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::srgb_u8(0, 0, 0),
+                                                ..default()
+                                            },
+                                        ));
+                                    });
+                            }
+                        });
+                });
+        });
+
+    // draw offer window
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Vw(100.0),
+                    height: Val::Vh(100.0),
                     display: Display::None,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
                     ..default()
                 },
                 ..default()
             },
-            PromotionPopupWindow,
+            DrawOfferWindow,
+            OnGameScreen,
         ))
         .with_children(|parent| {
             parent
@@ -184,7 +545,7 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 })
                 .with_children(|parent| {
                     parent.spawn(TextBundle::from_section(
-                        "Choose promotion",
+                        "Opponent offers a draw",
                         TextStyle {
                             font_size: 24.0,
                             color: Color::srgb_u8(0, 0, 0),
@@ -203,60 +564,498 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ..default()
                         })
                         .with_children(|parent| {
-                            let button_bundle = ButtonBundle {
-                                style: Style {
-                                    width: Val::Px(64.0),
-                                    aspect_ratio: Some(1.0),
-                                    padding: UiRect::all(Val::Px(8.0)),
-                                    ..default()
-                                },
-                                border_radius: BorderRadius::all(Val::Px(6.0)),
-                                background_color: Srgba::rgb_u8(255, 255, 255).into(),
-                                ..default()
-                            };
-
-                            let buttons = [
-                                (PromotionMenuAction::Knight, "sprites/white_knight.png"),
-                                (PromotionMenuAction::Bishop, "sprites/white_bishop.png"),
-                                (PromotionMenuAction::Rook, "sprites/white_rook.png"),
-                                (PromotionMenuAction::Queen, "sprites/white_queen.png"),
-                            ];
-
-                            for (action, texture) in buttons.iter() {
+                            for (action, label) in [
+                                (DrawOfferAction::Accept, "Accept"),
+                                (DrawOfferAction::Decline, "Decline"),
+                            ] {
                                 parent
-                                    .spawn((button_bundle.clone(), *action))
+                                    .spawn((dialog_button_bundle(), action))
                                     .with_children(|parent| {
-                                        parent.spawn(ImageBundle {
-                                            image: UiImage::new(asset_server.load(*texture)),
-                                            ..default()
-                                        });
+                                        parent.spawn(TextBundle::from_section(
+                                            label,
+                                            TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::srgb_u8(0, 0, 0),
+                                                ..default()
+                                            },
+                                        ));
                                     });
                             }
                         });
                 });
         });
-}
 
-pub fn update_ui(
-    mut text_query: Query<(&mut Text, Option<&TurnText>, Option<&GameStateText>)>,
-    mut windows_query: Query<(
-        &mut Style,
-        Option<&GameStatePopupWindow>,
-        Option<&PromotionPopupWindow>,
-        Option<&WaitingForOpponentWindow>,
-    )>,
-    mut game_state: ResMut<ClientGameState>,
-) {
-    for (mut text, turn_text, game_state_text) in text_query.iter_mut() {
-        // Update turn text
-        if turn_text.is_some() {
-            text.sections[0].value = format!(
-                "{}'s turn (we are {})",
-                match game_state.board_state.current_side() {
-                    PieceColor::White => "White",
-                    PieceColor::Black => "Black",
+    // takeback request window
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Vw(100.0),
+                    height: Val::Vh(100.0),
+                    display: Display::None,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
                 },
-                match game_state.own_color {
+                ..default()
+            },
+            TakebackWindow,
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(12.0)),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        display: Display::Flex,
+                        row_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                    border_radius: BorderRadius::all(Val::Px(6.0)),
+                    background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 24.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        TakebackRequestText,
+                    ));
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                display: Display::Flex,
+                                column_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, label) in [
+                                (TakebackAction::Accept, "Accept"),
+                                (TakebackAction::Decline, "Decline"),
+                            ] {
+                                parent
+                                    .spawn((dialog_button_bundle(), action))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            label,
+                                            TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::srgb_u8(0, 0, 0),
+                                                ..default()
+                                            },
+                                        ));
+                                    });
+                            }
+                        });
+                });
+        });
+
+    // opponent not responding window
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Vw(100.0),
+                    height: Val::Vh(100.0),
+                    display: Display::None,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            NotRespondingWindow,
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(12.0)),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        display: Display::Flex,
+                        row_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                    border_radius: BorderRadius::all(Val::Px(6.0)),
+                    background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Opponent not responding",
+                        TextStyle {
+                            font_size: 24.0,
+                            color: Color::srgb_u8(0, 0, 0),
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        NotRespondingText,
+                    ));
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                display: Display::Flex,
+                                column_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, label) in [
+                                (NotRespondingAction::ClaimWin, "Claim win"),
+                                (NotRespondingAction::KeepWaiting, "Keep waiting"),
+                            ] {
+                                parent
+                                    .spawn((dialog_button_bundle(), action))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            label,
+                                            TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::srgb_u8(0, 0, 0),
+                                                ..default()
+                                            },
+                                        ));
+                                    });
+                            }
+                        });
+                });
+        });
+
+    // promotion window
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Vw(100.0),
+                    height: Val::Vh(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    display: Display::None,
+                    ..default()
+                },
+                ..default()
+            },
+            PromotionPopupWindow,
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(12.0)),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        display: Display::Flex,
+                        row_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                    border_radius: BorderRadius::all(Val::Px(6.0)),
+                    background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Choose promotion",
+                        TextStyle {
+                            font_size: 24.0,
+                            color: Color::srgb_u8(0, 0, 0),
+                            ..default()
+                        },
+                    ));
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                display: Display::Flex,
+                                column_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            let button_bundle = ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(64.0),
+                                    aspect_ratio: Some(1.0),
+                                    padding: UiRect::all(Val::Px(8.0)),
+                                    ..default()
+                                },
+                                border_radius: BorderRadius::all(Val::Px(6.0)),
+                                background_color: Srgba::rgb_u8(255, 255, 255).into(),
+                                ..default()
+                            };
+
+                            let buttons = [
+                                (PromotionMenuAction::Knight, "sprites/white_knight.png"),
+                                (PromotionMenuAction::Bishop, "sprites/white_bishop.png"),
+                                (PromotionMenuAction::Rook, "sprites/white_rook.png"),
+                                (PromotionMenuAction::Queen, "sprites/white_queen.png"),
+                            ];
+
+                            for (action, texture) in buttons.iter() {
+                                parent
+                                    .spawn((button_bundle.clone(), *action))
+                                    .with_children(|parent| {
+                                        parent.spawn(ImageBundle {
+                                            image: UiImage::new(asset_server.load(*texture)),
+                                            ..default()
+                                        });
+                                    });
+                            }
+                        });
+                });
+        });
+
+    // chat panel
+    commands.insert_resource(ChatView::default());
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(12.0),
+                    right: Val::Px(12.0),
+                    width: Val::Px(320.0),
+                    padding: UiRect::all(Val::Px(12.0)),
+                    flex_direction: FlexDirection::Column,
+                    display: Display::None,
+                    row_gap: Val::Px(6.0),
+                    ..default()
+                },
+                border_radius: BorderRadius::all(Val::Px(6.0)),
+                background_color: Srgba::rgba_u8(255, 255, 255, 100).into(),
+                ..default()
+            },
+            ChatWindow,
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((dialog_button_bundle(), ChatAction::Toggle))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Chat",
+                            TextStyle {
+                                font_size: 20.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        ChatToggleText,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            display: Display::None,
+                            row_gap: Val::Px(6.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    ChatBody,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(0, 0, 0),
+                                ..default()
+                            },
+                        ),
+                        ChatLogText,
+                    ));
+
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                padding: UiRect::all(Val::Px(5.0)),
+                                display: Display::Flex,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            border_radius: BorderRadius::all(Val::Px(6.0)),
+                            background_color: Srgba::rgb_u8(100, 100, 100).into(),
+                            ..default()
+                        },
+                        TextInputBundle::default()
+                            .with_text_style(TextStyle {
+                                font_size: 16.0,
+                                ..default()
+                            })
+                            .with_placeholder("Say something", None)
+                            .with_inactive(true),
+                        ChatInput,
+                    ));
+                });
+        });
+}
+
+/// The last lines of the chat with who said them
+fn chat_log(game_state: &ClientGameState, network_handler: &NetworkHandler) -> String {
+    const SHOWN_LINES: usize = 10;
+
+    let own_name = network_handler.display_name();
+    let opponent_name = network_handler
+        .opponent_name
+        .clone()
+        .unwrap_or("anonymous".to_string());
+
+    let skipped = game_state.chat.len().saturating_sub(SHOWN_LINES);
+    game_state.chat[skipped..]
+        .iter()
+        .map(|line| {
+            let name = if line.own { &own_name } else { &opponent_name };
+            format!("{}: {}", name, line.text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The last moves of the game numbered like a score sheet, one full move per line
+fn move_list(game_state: &ClientGameState) -> String {
+    const SHOWN_LINES: usize = 12;
+
+    let lines = game_state.numbered_moves();
+    let skipped = lines.len().saturating_sub(SHOWN_LINES);
+    lines[skipped..].join("\n")
+}
+
+fn dialog_button_bundle() -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        border_radius: BorderRadius::all(Val::Px(6.0)),
+        background_color: Srgba::rgb_u8(255, 255, 255).into(),
+        ..default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_ui(
+    mut text_query: Query<(
+        &mut Text,
+        Option<&TurnText>,
+        Option<&GameStateText>,
+        Option<&ConnectionLostText>,
+        Option<&OfferDrawText>,
+        Option<&ClockText>,
+        Option<&OpponentText>,
+        Option<&NotRespondingText>,
+        Option<&RematchText>,
+        Option<&MoveListText>,
+    )>,
+    mut windows_query: Query<
+        (
+            &mut Style,
+            Option<&GameStatePopupWindow>,
+            Option<&PromotionPopupWindow>,
+            Option<&WaitingForOpponentWindow>,
+            Option<&ConnectionLostWindow>,
+            Option<&DrawOfferWindow>,
+            Option<&NotRespondingWindow>,
+        ),
+        (Without<ConnectionLostAction>, Without<GameOverAction>),
+    >,
+    mut button_query: Query<
+        (
+            &mut Style,
+            Option<&ConnectionLostAction>,
+            Option<&GameOverAction>,
+        ),
+        Or<(With<ConnectionLostAction>, With<GameOverAction>)>,
+    >,
+    game_state: Res<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+    reconnection: Option<Res<Reconnection>>,
+    self_play: Option<Res<SelfPlay>>,
+    engine: Option<Res<EngineOpponent>>,
+    hot_seat: Option<Res<HotSeat>>,
+) {
+    let game_over = game_state.is_game_over();
+    let disconnected = game_state
+        .result
+        .is_some_and(|result| result.reason == EndReason::Disconnect);
+    // a finished game doesn't care about the connection anymore, unless losing it is what ended it
+    let connection_lost = network_handler.error.is_some() && (!game_over || disconnected);
+    let reconnecting = reconnection.is_some();
+    let unresponsive = game_state.opponent_unresponsive() && network_handler.error.is_none();
+    let spectating = network_handler.spectating;
+    // playing yourself there's nobody to ask, and spectators have no say
+    let can_rematch = network_handler.connection.is_some()
+        && self_play.is_none()
+        && !spectating
+        && !game_state.rematch_requested;
+
+    for (
+        mut text,
+        turn_text,
+        game_state_text,
+        connection_lost_text,
+        offer_draw_text,
+        clock_text,
+        opponent_text,
+        not_responding_text,
+        rematch_text,
+        move_list_text,
+    ) in text_query.iter_mut()
+    {
+        // Update turn text
+        if turn_text.is_some() && spectating {
+            text.sections[0].value = format!(
+                "{}'s turn (spectating)",
+                color_name(game_state.board_state.current_side())
+            );
+        } else if turn_text.is_some() && hot_seat.is_some() {
+            text.sections[0].value = format!(
+                "{}'s turn",
+                color_name(game_state.board_state.current_side())
+            );
+        } else if turn_text.is_some() {
+            text.sections[0].value = format!(
+                "{}'s turn (we are {})",
+                match game_state.board_state.current_side() {
+                    PieceColor::White => "White",
+                    PieceColor::Black => "Black",
+                },
+                match game_state.own_color {
                     PieceColor::White => "White",
                     PieceColor::Black => "Black",
                 }
@@ -265,31 +1064,115 @@ pub fn update_ui(
 
         // Update game state text
         if game_state_text.is_some() {
-            if let Some(next_ack_state) = &game_state.next_ack_state {
-                text.sections[0].value = match next_ack_state {
-                    chess_networking::GameState::CheckMate => "Checkmate",
-                    chess_networking::GameState::Draw => "Draw",
+            text.sections[0].value = game_state
+                .result
+                .map(|result| result.to_string())
+                .unwrap_or_default();
+        }
+
+        if let Some(ClockText(color)) = clock_text {
+            text.sections[0].value = match &game_state.clock {
+                Some(clock) => {
+                    let remaining = clock.remaining(*color).as_secs();
+                    format!(
+                        "{}: {}:{:02}",
+                        color_name(*color),
+                        remaining / 60,
+                        remaining % 60
+                    )
                 }
-                .to_string();
+                None => String::new(),
+            };
+        }
+
+        if opponent_text.is_some() && hot_seat.is_some() {
+            text.sections[0].value = "Local game".to_string();
+        } else if opponent_text.is_some() {
+            text.sections[0].value = format!(
+                "{} {}",
+                if spectating {
+                    "Watching"
+                } else {
+                    "Playing against"
+                },
+                network_handler
+                    .opponent_name
+                    .as_deref()
+                    .unwrap_or("anonymous")
+            );
+        }
+
+        if offer_draw_text.is_some() {
+            text.sections[0].value = if game_state.offer_draw {
+                "Draw offered"
             } else {
-                text.sections[0].value = match game_state.board_state.check_game_state() {
-                    GameState::Playing => "",
-                    GameState::Checkmate => "Checkmate",
-                    GameState::Stalemate => "Stalemate",
-                    GameState::DrawByRepetition => "Draw",
-                    GameState::DrawByInsufficientMaterial => "Draw",
-                }
-                .to_string();
+                "Offer draw"
+            }
+            .to_string();
+        }
+
+        if move_list_text.is_some() {
+            text.sections[0].value = move_list(&game_state);
+        }
+
+        if rematch_text.is_some() {
+            text.sections[0].value = if self_play.is_some() || hot_seat.is_some() || spectating {
+                ""
+            } else if network_handler.connection.is_none() {
+                "Opponent left"
+            } else if game_state.rematch_requested {
+                "Waiting for the opponent to accept the rematch"
+            } else if game_state.rematch_start.is_some() {
+                "Opponent wants a rematch"
+            } else {
+                ""
+            }
+            .to_string();
+        }
+
+        if not_responding_text.is_some() && unresponsive {
+            text.sections[0].value = format!(
+                "No answer for {} seconds",
+                game_state.opponent_silence.as_secs()
+            );
+        }
+
+        if connection_lost_text.is_some() {
+            if let (Some(result), true) = (game_state.result, disconnected) {
+                text.sections[0].value = result.to_string();
+            } else if reconnecting {
+                text.sections[0].value = match network_handler.role {
+                    NetworkRole::Server => format!(
+                        "Connection lost, waiting for {} to reconnect...",
+                        network_handler
+                            .opponent_name
+                            .as_deref()
+                            .unwrap_or("the opponent")
+                    ),
+                    NetworkRole::Client => "Connection lost, reconnecting...".to_string(),
+                };
+            } else if let Some(error) = &network_handler.error {
+                text.sections[0].value = error.to_string();
             }
         }
     }
 
-    for (mut style, game_state_wnd, promotion_wnd, opponent_wnd) in windows_query.iter_mut() {
+    for (
+        mut style,
+        game_state_wnd,
+        promotion_wnd,
+        opponent_wnd,
+        connection_lost_wnd,
+        draw_wnd,
+        not_responding_wnd,
+    ) in windows_query.iter_mut()
+    {
         if game_state_wnd.is_some() {
             // popup window logic
-            style.display = match game_state.board_state.check_game_state() {
-                GameState::Playing => Display::None,
-                _ => Display::Flex,
+            style.display = if game_over && !connection_lost {
+                Display::Flex
+            } else {
+                Display::None
             };
         }
 
@@ -303,17 +1186,301 @@ pub fn update_ui(
 
         if opponent_wnd.is_some() {
             if game_state.board_state.current_side() != game_state.own_color
-                && match game_state.board_state.check_game_state() {
-                    // why no partialeq wtf :sob: :sob: :sob:
-                    GameState::Playing => true,
-                    _ => false,
-                }
+                && !spectating
+                && network_handler.error.is_none()
+                && !game_over
+                && !unresponsive
             {
                 style.display = Display::Flex;
             } else {
                 style.display = Display::None;
             }
         }
+
+        if connection_lost_wnd.is_some() {
+            style.display = if connection_lost {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+
+        if not_responding_wnd.is_some() {
+            style.display = if unresponsive {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+
+        if draw_wnd.is_some() {
+            style.display = if game_state.incoming_draw_offer && network_handler.error.is_none() {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+
+    // only a game that's still going can be reconnected to or claimed
+    for (mut style, connection_lost_action, game_over_action) in button_query.iter_mut() {
+        let shown = match (connection_lost_action, game_over_action) {
+            (Some(ConnectionLostAction::Reconnect), _) => {
+                !game_over
+                    && !reconnecting
+                    && !spectating
+                    && network_handler.lobby_address.is_none()
+                    && engine.is_none()
+            }
+            (Some(ConnectionLostAction::ClaimWin), _) => !game_over && !spectating,
+            (_, Some(GameOverAction::Rematch)) => can_rematch,
+            _ => true,
+        };
+        style.display = if shown { Display::Flex } else { Display::None };
+    }
+}
+
+pub(crate) fn game_over_action(
+    action_query: Query<(&GameOverAction, &Interaction), Changed<Interaction>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed || !game_state.is_game_over() {
+            continue;
+        }
+
+        match action {
+            GameOverAction::Rematch => {
+                if game_state.rematch_requested || network_handler.connection.is_none() {
+                    continue;
+                }
+                game_state.rematch_requested = true;
+
+                // the client asks like it would for a new game, the host answers once it agrees
+                if network_handler.role == NetworkRole::Client {
+                    let start = client_start(&network_handler.display_name());
+                    network_handler.send(Message::start(start));
+                }
+            }
+            GameOverAction::MainMenu => {
+                app_state.set(AppState::MainMenu);
+            }
+        }
+    }
+}
+
+pub(crate) fn game_action(
+    action_query: Query<(&GameAction, &Interaction), Changed<Interaction>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+    peer_capabilities: Res<PeerCapabilities>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed
+            || game_state.is_game_over()
+            || network_handler.error.is_some()
+        {
+            continue;
+        }
+
+        match action {
+            GameAction::Resign => {
+                let winner = other_color(game_state.own_color);
+                game_state.result = Some(GameResult::win(winner, EndReason::Forfeit));
+                game_state.pending_promotion_move = None;
+
+                network_handler.send(Message::Move(chess_networking::Move {
+                    from: (0, 0),
+                    to: (0, 0),
+                    promotion: None,
+                    forfeit: true,
+                    offer_draw: false,
+                }));
+            }
+            GameAction::OfferDraw => {
+                // offers go out with our next move, clicking again takes it back
+                game_state.offer_draw = !game_state.offer_draw;
+            }
+            GameAction::Takeback => {
+                if !peer_capabilities.supports(extension::TAKEBACK) {
+                    continue;
+                }
+
+                if let Some(request) = game_state.request_takeback() {
+                    network_handler.send(Message::Extension(request));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn takeback_action(
+    action_query: Query<(&TakebackAction, &Interaction), Changed<Interaction>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let accept = matches!(action, TakebackAction::Accept);
+        if let Some(answer) = game_state.answer_takeback(accept) {
+            network_handler.send(Message::Extension(answer));
+        }
+    }
+}
+
+/// Shows the takeback button if the opponent does takebacks, and their requests
+pub(crate) fn update_takeback(
+    game_state: Res<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+    peer_capabilities: Res<PeerCapabilities>,
+    mut button_query: Query<(&mut Style, &GameAction), Without<TakebackWindow>>,
+    mut window_query: Query<&mut Style, With<TakebackWindow>>,
+    mut text_query: Query<(
+        &mut Text,
+        Option<&TakebackText>,
+        Option<&TakebackRequestText>,
+    )>,
+) {
+    let available = peer_capabilities.supports(extension::TAKEBACK);
+    for (mut style, action) in button_query.iter_mut() {
+        if let GameAction::Takeback = action {
+            style.display = if available {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+
+    let incoming = game_state
+        .takeback
+        .filter(|takeback| takeback.incoming && network_handler.error.is_none());
+    for mut style in window_query.iter_mut() {
+        style.display = if incoming.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    for (mut text, button_text, request_text) in text_query.iter_mut() {
+        if button_text.is_some() {
+            text.sections[0].value = match game_state.takeback {
+                Some(takeback) if !takeback.incoming => "Takeback asked...",
+                _ => "Take back",
+            }
+            .to_string();
+        }
+
+        if let (Some(takeback), Some(_)) = (incoming, request_text) {
+            text.sections[0].value = match takeback.plies {
+                1 => "Opponent asks to take back their last move".to_string(),
+                plies => format!("Opponent asks to take back the last {} moves", plies),
+            };
+        }
+    }
+}
+
+pub(crate) fn draw_offer_action(
+    action_query: Query<(&DrawOfferAction, &Interaction), Changed<Interaction>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed || !game_state.incoming_draw_offer {
+            continue;
+        }
+
+        if let DrawOfferAction::Accept = action {
+            game_state.result = Some(GameResult::draw(EndReason::Agreement));
+        }
+
+        // now the move that came with the offer can be acked
+        game_state.incoming_draw_offer = false;
+        game_state.network_state = NetworkState::Normal;
+
+        network_handler.send(Message::Ack(chess_networking::Ack {
+            ok: true,
+            end_state: game_state.result.and_then(|result| result.end_state()),
+        }));
+    }
+}
+
+pub(crate) fn not_responding_action(
+    action_query: Query<(&NotRespondingAction, &Interaction), Changed<Interaction>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed || !game_state.opponent_unresponsive() {
+            continue;
+        }
+
+        match action {
+            NotRespondingAction::ClaimWin => game_state.claim_win(&mut network_handler.connection),
+            // starts the timeout over
+            NotRespondingAction::KeepWaiting => game_state.opponent_silence = Duration::ZERO,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn connection_lost_action(
+    mut commands: Commands,
+    action_query: Query<(&ConnectionLostAction, &Interaction), Changed<Interaction>>,
+    mut saved_text_query: Query<&mut Text, With<SavedPositionText>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+    host_settings: Res<HostSettings>,
+    reconnection: Option<Res<Reconnection>>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match action {
+            ConnectionLostAction::Reconnect => {
+                if reconnection.is_none() && !game_state.is_game_over() {
+                    start_reconnect(&mut commands, &network_handler, &host_settings, &game_state);
+                }
+            }
+            ConnectionLostAction::ClaimWin => {
+                if !game_state.is_game_over() {
+                    let own_color = game_state.own_color;
+                    game_state.result = Some(GameResult::win(own_color, EndReason::Disconnect));
+                    game_state.pending_promotion_move = None;
+                    // nobody to come back to anymore
+                    commands.remove_resource::<Reconnection>();
+                }
+            }
+            ConnectionLostAction::SavePosition => {
+                let fen = position_to_fen(&game_state.board_state, game_state.last_move);
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                let path = format!("{}/position_{}.fen", SAVE_DIR, timestamp);
+
+                let result = std::fs::create_dir_all(SAVE_DIR)
+                    .and_then(|_| std::fs::write(&path, format!("{}\n", fen)));
+
+                for mut text in saved_text_query.iter_mut() {
+                    text.sections[0].value = match &result {
+                        Ok(_) => format!("Saved to {}", path),
+                        Err(e) => format!("Failed to save position: {}", e),
+                    };
+                }
+            }
+            ConnectionLostAction::MainMenu => {
+                app_state.set(AppState::MainMenu);
+            }
+        }
     }
 }
 
@@ -332,30 +1499,106 @@ pub(crate) fn promotion_menu_action(
                     PromotionMenuAction::Rook => PieceType::Rook,
                     PromotionMenuAction::Queen => PieceType::Queen,
                 });
-                game_state.board_state.make_move(m);
-                game_state.network_state = NetworkState::AwaitingAck;
-                game_state.last_move = Some(m);
-                game_state.board_dirty = true;
-                game_state.pending_promotion_move = None;
-
-                let move_packet = chess_networking::Move {
-                    from: (m.from() as u8 % 8, 7 - (m.from() as u8 / 8)),
-                    to: (m.to() as u8 % 8, 7 - (m.to() as u8 / 8)),
-                    promotion: match m.promotion_piece() {
-                        PieceType::Knight => Some(PromotionPiece::Knight),
-                        PieceType::Bishop => Some(PromotionPiece::Bishop),
-                        PieceType::Rook => Some(PromotionPiece::Rook),
-                        PieceType::Queen => Some(PromotionPiece::Queen),
-                        _ => None,
-                    },
-                    forfeit: false,
-                    offer_draw: false,
-                };
 
-                if let Some(connection) = network_handler.connection.as_mut() {
-                    connection.write(move_packet.try_into().unwrap());
-                }
+                let move_packet = game_state.play_own_move(m);
+                network_handler.send(Message::Move(move_packet));
             }
         }
     }
 }
+
+pub(crate) fn chat_action(
+    action_query: Query<(&ChatAction, &Interaction), Changed<Interaction>>,
+    mut chat_view: ResMut<ChatView>,
+) {
+    for (action, interaction) in &action_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match action {
+            ChatAction::Toggle => chat_view.open = !chat_view.open,
+        }
+    }
+}
+
+pub(crate) fn send_chat(
+    mut submit_events: EventReader<TextInputSubmitEvent>,
+    input_query: Query<(), With<ChatInput>>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+    peer_capabilities: Res<PeerCapabilities>,
+) {
+    const MAX_LENGTH: usize = 500;
+
+    for event in submit_events.read() {
+        if !input_query.contains(event.entity)
+            || !peer_capabilities.supports(extension::CHAT)
+            || network_handler.connection.is_none()
+        {
+            continue;
+        }
+
+        let text: String = event.value.trim().chars().take(MAX_LENGTH).collect();
+        if text.is_empty() {
+            continue;
+        }
+
+        network_handler.send(Message::Extension(Extension::Chat(text.clone())));
+        game_state.chat.push(ChatLine { own: true, text });
+    }
+}
+
+/// Shows the chat panel if the opponent chats, with the unread lines counted while it's closed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_chat(
+    mut chat_view: ResMut<ChatView>,
+    game_state: Res<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+    peer_capabilities: Res<PeerCapabilities>,
+    mut window_query: Query<&mut Style, (With<ChatWindow>, Without<ChatBody>)>,
+    mut body_query: Query<&mut Style, (With<ChatBody>, Without<ChatWindow>)>,
+    mut text_query: Query<
+        (&mut Text, Option<&ChatLogText>),
+        Or<(With<ChatLogText>, With<ChatToggleText>)>,
+    >,
+    mut input_query: Query<&mut TextInputInactive, With<ChatInput>>,
+) {
+    let available = peer_capabilities.supports(extension::CHAT) && !network_handler.spectating;
+    for mut style in window_query.iter_mut() {
+        style.display = if available {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let open = available && chat_view.open;
+    if open {
+        chat_view.seen = game_state.chat.len();
+    }
+
+    for mut style in body_query.iter_mut() {
+        style.display = if open { Display::Flex } else { Display::None };
+    }
+
+    // typing only goes to the chat while it's open
+    for mut inactive in input_query.iter_mut() {
+        if inactive.0 == open {
+            inactive.0 = !open;
+        }
+    }
+
+    for (mut text, log) in text_query.iter_mut() {
+        text.sections[0].value = if log.is_some() {
+            chat_log(&game_state, &network_handler)
+        } else if open {
+            "Hide chat".to_string()
+        } else {
+            match game_state.chat.len().saturating_sub(chat_view.seen) {
+                0 => "Chat".to_string(),
+                unread => format!("Chat ({} new)", unread),
+            }
+        };
+    }
+}
@@ -0,0 +1,145 @@
+use std::{path::Path, sync::atomic::AtomicBool};
+
+use serde::{Deserialize, Serialize};
+
+use crate::general::resources::{HostColor, HostSettings, NetworkRole};
+
+use super::extension::Extension;
+use super::networking::{self, Connection, Handshake, Message, NetworkError, Transport};
+
+/// The lobby server listens here unless told otherwise, see `src/bin/lobby_server.rs`
+pub(crate) const LOBBY_PORT: u16 = 22030;
+
+/// A game in the lobby waiting for an opponent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpenGame {
+    pub id: u32,
+    pub host: String,
+    /// Seconds per side and the increment like in `TimeControl`, `None` for games without a clock
+    pub time: Option<u64>,
+    pub inc: Option<u64>,
+    /// The color the host plays, `None` if it's picked when the game starts
+    pub host_is_white: Option<bool>,
+}
+
+/// What players and the lobby server say to each other before a game is paired. After
+/// `Paired` the lobby only relays, so the players run the usual `Start` handshake through it
+/// with the host as the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum LobbyMessage {
+    /// Asks for the open games, answered with `Games`
+    List,
+    Games(Vec<OpenGame>),
+    /// Opens a game, answered with `Hosted`
+    Host {
+        name: String,
+        time: Option<u64>,
+        inc: Option<u64>,
+        host_is_white: Option<bool>,
+    },
+    Hosted {
+        id: u32,
+    },
+    Join {
+        id: u32,
+        name: String,
+    },
+    /// Sent to both players once someone joined a game
+    Paired {
+        opponent: String,
+    },
+    /// The lobby couldn't do what was asked, it closes the connection after this
+    Refused(String),
+}
+
+fn send(connection: &mut dyn Transport, message: LobbyMessage) -> Result<(), NetworkError> {
+    connection.write(Message::Extension(Extension::Lobby(message)))
+}
+
+/// The next message from the lobby, `None` if we were cancelled first
+fn receive(
+    connection: &mut dyn Transport,
+    cancel: &AtomicBool,
+) -> Result<Option<LobbyMessage>, NetworkError> {
+    match connection.read_blocking(cancel)? {
+        Some(Message::Extension(Extension::Lobby(LobbyMessage::Refused(reason)))) => {
+            Err(NetworkError::Refused(reason))
+        }
+        Some(Message::Extension(Extension::Lobby(message))) => Ok(Some(message)),
+        Some(_) => Err(NetworkError::UnexpectedPacket),
+        None => Ok(None),
+    }
+}
+
+/// Asks the lobby at `address` which games are open, over a connection of its own. Blocks, so
+/// it's run on a `ConnectThread`.
+pub(crate) fn list_games(
+    address: &str,
+    cancel: &AtomicBool,
+) -> Result<Option<Vec<OpenGame>>, NetworkError> {
+    let mut connection = Connection::connect(address, LOBBY_PORT)?;
+    send(&mut connection, LobbyMessage::List)?;
+
+    match receive(&mut connection, cancel)? {
+        Some(LobbyMessage::Games(games)) => Ok(Some(games)),
+        Some(_) => Err(NetworkError::UnexpectedPacket),
+        None => Ok(None),
+    }
+}
+
+/// Like `networking::establish` but through the lobby at `address`. Without a `game` we open one
+/// and wait for an opponent as the server, with one we join it as the client.
+pub(crate) fn establish(
+    address: &str,
+    game: Option<u32>,
+    name: &str,
+    settings: &HostSettings,
+    capture: Option<&Path>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    let mut connection: Box<dyn Transport> = Box::new(Connection::connect(address, LOBBY_PORT)?);
+
+    let (role, request) = match game {
+        None => (
+            NetworkRole::Server,
+            LobbyMessage::Host {
+                name: name.to_string(),
+                time: settings.time_control.map(|time_control| time_control.time),
+                inc: settings.time_control.map(|time_control| time_control.inc),
+                host_is_white: match settings.color {
+                    HostColor::White => Some(true),
+                    HostColor::Black => Some(false),
+                    HostColor::Random => None,
+                },
+            },
+        ),
+        Some(id) => (
+            NetworkRole::Client,
+            LobbyMessage::Join {
+                id,
+                name: name.to_string(),
+            },
+        ),
+    };
+    send(connection.as_mut(), request)?;
+
+    loop {
+        match receive(connection.as_mut(), cancel)? {
+            Some(LobbyMessage::Hosted { id }) => println!("Hosting game {} in the lobby", id),
+            Some(LobbyMessage::Paired { opponent }) => {
+                println!("Paired with {} in the lobby", opponent);
+                break;
+            }
+            Some(_) => return Err(NetworkError::UnexpectedPacket),
+            None => return Ok(None),
+        }
+    }
+
+    // the capture starts at the game so it can be replayed without a lobby
+    if let Some(path) = capture {
+        connection = networking::record(connection, path, role)?;
+    }
+
+    // the lobby pairs anyone who asks, so there's no password to check
+    networking::handshake(role, connection, name, settings, None, cancel)
+}
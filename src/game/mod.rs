@@ -6,14 +6,36 @@ use resources::*;
 
 pub mod networking;
 
+pub mod discovery;
+
+pub mod capture;
+
+pub(crate) mod extension;
+
+pub(crate) mod lobby;
+
+pub(crate) mod record;
+
+pub(crate) mod spectators;
+
+pub(crate) mod engine;
+
+pub(crate) mod computer;
+
 mod systems;
-use systems::{board, input, resource_setup, setup};
+use systems::{
+    board, capabilities, clock, hot_seat, input, liveness, reconnect, rematch, resource_setup,
+    self_play, setup, spectate,
+};
 
 mod utils;
 use utils::*;
 
 mod game_ui;
 
+#[cfg(test)]
+mod protocol_tests;
+
 use bevy::prelude::*;
 
 use crate::{despawn_screen, GameState};
@@ -28,18 +50,70 @@ pub fn game_plugin(app: &mut App) {
             resource_setup::setup,
             setup::setup_game_scene.after(resource_setup::setup),
             game_ui::setup_ui.after(resource_setup::setup),
+            spectate::open_for_spectators,
         ),
     )
     .add_systems(
         Update,
         (
-            input::handle_picking.run_if(in_state(GameState::InGame)),
+            input::handle_picking
+                .run_if(in_state(GameState::InGame))
+                .run_if(not(spectate::spectating)),
             game_ui::update_ui.run_if(in_state(GameState::InGame)),
             game_ui::promotion_menu_action.run_if(in_state(GameState::InGame)),
+            game_ui::connection_lost_action.run_if(in_state(GameState::InGame)),
+            game_ui::game_action.run_if(in_state(GameState::InGame)),
+            game_ui::draw_offer_action.run_if(in_state(GameState::InGame)),
+            game_ui::not_responding_action.run_if(in_state(GameState::InGame)),
+            game_ui::game_over_action.run_if(in_state(GameState::InGame)),
+            (
+                game_ui::chat_action,
+                game_ui::send_chat,
+                game_ui::update_chat,
+            )
+                .run_if(in_state(GameState::InGame)),
+            (game_ui::takeback_action, game_ui::update_takeback)
+                .run_if(in_state(GameState::InGame)),
             board::update_board.run_if(in_state(GameState::InGame)),
-            board::wait_for_move.run_if(in_state(GameState::InGame)),
+            board::wait_for_move
+                .run_if(in_state(GameState::InGame))
+                .run_if(not(spectate::spectating)),
+            capabilities::receive_capabilities.run_if(in_state(GameState::InGame)),
+            clock::tick_clock.run_if(in_state(GameState::InGame)),
+            liveness::watch_opponent
+                .run_if(in_state(GameState::InGame))
+                .run_if(not(spectate::spectating)),
+            reconnect::finish_reconnect
+                .run_if(in_state(GameState::InGame))
+                .run_if(resource_exists::<reconnect::Reconnection>),
+            rematch::start_rematch
+                .run_if(in_state(GameState::InGame))
+                .run_if(not(resource_exists::<SelfPlay>))
+                .run_if(not(spectate::spectating)),
+            spectate::serve_spectators
+                .run_if(in_state(GameState::InGame))
+                .run_if(resource_exists::<spectators::Spectators>),
+            spectate::watch_game
+                .run_if(in_state(GameState::InGame))
+                .run_if(spectate::spectating),
+            (
+                self_play::play_other_side.run_if(resource_exists::<SelfPlay>),
+                (
+                    // before the ui would show the player waiting for an opponent
+                    hot_seat::pass_turn
+                        .after(input::handle_picking)
+                        .after(game_ui::promotion_menu_action)
+                        .before(game_ui::update_ui),
+                    hot_seat::turn_camera,
+                )
+                    .run_if(resource_exists::<HotSeat>),
+            )
+                .run_if(in_state(GameState::InGame)),
         ),
     )
     .insert_resource(ClearColor(Color::srgb_u8(77, 79, 84)))
-    .add_systems(OnExit(GameState::InGame), despawn_screen::<OnGameScreen>);
+    .add_systems(
+        OnExit(GameState::InGame),
+        (despawn_screen::<OnGameScreen>, setup::cleanup_game),
+    );
 }
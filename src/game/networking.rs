@@ -1,64 +1,1450 @@
 use std::{
-    io::{Read, Write},
-    net::{TcpListener, TcpStream},
-    time::Duration,
+    collections::{hash_map::RandomState, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    io::{ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use chess_networking::PromotionPiece;
+use socket2::{SockRef, TcpKeepalive};
+use vhultman_chess::Position;
+
+use crate::general::resources::{HostSettings, NetworkRole};
+
+use super::capture::Capture;
+use super::extension::{self, Extension};
+
+/// Frames bigger than this are treated as garbage instead of waiting for the rest
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+pub(crate) const DEFAULT_PORT: u16 = 22022;
+
+/// Connecting to an address that doesn't answer gives up after this long, instead of waiting
+/// for the os which can take minutes
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The os starts probing a connection after it has been quiet for this long, and gives up on it
+/// after `KEEPALIVE_RETRIES` unanswered probes `KEEPALIVE_INTERVAL` apart. That catches peers
+/// that went away without closing the connection, like a machine going to sleep.
+const KEEPALIVE_TIME: Duration = Duration::from_secs(15);
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const KEEPALIVE_RETRIES: u32 = 4;
+
+/// Spectators of a game hosted on some port connect to the port after it
+pub(crate) fn spectator_port(game_port: u16) -> u16 {
+    game_port.wrapping_add(1)
+}
+
+/// The spectator address for a game address the player typed in, or the input as it is if it
+/// doesn't resolve so connecting reports the error
+pub(crate) fn spectator_address(input: &str) -> String {
+    match resolve_address(input, DEFAULT_PORT) {
+        Ok(addresses) if !addresses.is_empty() => {
+            let mut address = addresses[0];
+            address.set_port(spectator_port(address.port()));
+            address.to_string()
+        }
+        _ => input.to_string(),
+    }
+}
+
+/// An ack should follow a move right away, this long without one means something is wrong
+pub(crate) const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Turns what the player typed into addresses to try. Accepts "ip:port", "[ipv6]:port", bare ip
+/// addresses (ipv6 with or without brackets) and hostnames with or without a port. An empty
+/// string means localhost.
+pub(crate) fn resolve_address(input: &str, default_port: u16) -> std::io::Result<Vec<SocketAddr>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(vec![SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            default_port,
+        )]);
+    }
+
+    if let Ok(address) = input.parse::<SocketAddr>() {
+        return Ok(vec![address]);
+    }
+
+    let unbracketed = input.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, default_port)]);
+    }
+
+    let host_and_port = input
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)));
+
+    let addresses: Vec<SocketAddr> = match host_and_port {
+        Some(host_and_port) => host_and_port.to_socket_addrs()?.collect(),
+        None => (input, default_port).to_socket_addrs()?.collect(),
+    };
+
+    if addresses.is_empty() {
+        return Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!("no addresses found for {}", input),
+        ));
+    }
+
+    Ok(addresses)
+}
+
+/// Addresses other players can probably reach us on when listening on `bind`. For unspecified
+/// addresses this asks the os which local address it would route outwards from, connecting a
+/// udp socket doesn't send anything.
+pub(crate) fn reachable_addresses(bind: SocketAddr) -> Vec<SocketAddr> {
+    if !bind.ip().is_unspecified() {
+        return vec![bind];
+    }
+
+    let mut addresses = vec![SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        bind.port(),
+    )];
+
+    let mut probes = vec![("0.0.0.0:0", "8.8.8.8:80")];
+    if bind.is_ipv6() {
+        probes.push(("[::]:0", "[2001:4860:4860::8888]:80"));
+    }
+
+    for (local, remote) in probes {
+        let ip = UdpSocket::bind(local)
+            .and_then(|socket| socket.connect(remote).map(|_| socket))
+            .and_then(|socket| socket.local_addr());
+
+        if let Ok(address) = ip {
+            addresses.push(SocketAddr::new(address.ip(), bind.port()));
+        }
+    }
+
+    addresses
+}
+
+/// A decoded chess_networking packet. The packets aren't tagged on the wire so decoding just
+/// tries each type, `Start` and `Move` first since they are stricter than `Ack`. Our own
+/// extensions come last, see `Extension`.
+#[derive(Debug)]
+pub(crate) enum Message {
+    /// With the extensions the sender supports, see `extension::add_to_start`
+    Start(chess_networking::Start, Vec<String>),
+    Move(chess_networking::Move),
+    Ack(chess_networking::Ack),
+    Extension(Extension),
+}
+
+impl Message {
+    /// A `Start` of ours, listing the extensions we support
+    pub fn start(packet: chess_networking::Start) -> Self {
+        Message::Start(packet, extension::supported())
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if let Ok(packet) = chess_networking::Start::try_from(buf) {
+            Some(Message::Start(packet, extension::from_start(buf)))
+        } else if let Ok(packet) = chess_networking::Move::try_from(buf) {
+            Some(Message::Move(packet))
+        } else if let Ok(packet) = chess_networking::Ack::try_from(buf) {
+            Some(Message::Ack(packet))
+        } else {
+            Extension::decode(buf).map(Message::Extension)
+        }
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, NetworkError> {
+        match self {
+            Message::Start(packet, extensions) => {
+                let mut buf: Vec<u8> = packet.try_into().map_err(|_| NetworkError::Encode)?;
+                extension::add_to_start(&mut buf, extensions)?;
+                Ok(buf)
+            }
+            Message::Move(packet) => packet.try_into().map_err(|_| NetworkError::Encode),
+            Message::Ack(packet) => packet.try_into().map_err(|_| NetworkError::Encode),
+            Message::Extension(extension) => extension.encode().ok_or(NetworkError::Encode),
+        }
+    }
+}
+
+// written out since not all the chess_networking packets are Clone
+impl Clone for Message {
+    fn clone(&self) -> Self {
+        match self {
+            Message::Start(packet, extensions) => {
+                Message::Start(packet.clone(), extensions.clone())
+            }
+            Message::Move(packet) => Message::Move(chess_networking::Move {
+                from: packet.from,
+                to: packet.to,
+                promotion: packet.promotion.as_ref().map(|piece| match piece {
+                    PromotionPiece::Queen => PromotionPiece::Queen,
+                    PromotionPiece::Rook => PromotionPiece::Rook,
+                    PromotionPiece::Bishop => PromotionPiece::Bishop,
+                    PromotionPiece::Knight => PromotionPiece::Knight,
+                }),
+                forfeit: packet.forfeit,
+                offer_draw: packet.offer_draw,
+            }),
+            Message::Ack(packet) => Message::Ack(packet.clone()),
+            Message::Extension(extension) => Message::Extension(extension.clone()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum FrameError {
+    InvalidMarker(u8),
+    TooLarge,
+}
+
+#[derive(Debug)]
+pub(crate) enum NetworkError {
+    /// Couldn't listen on the host address
+    Bind(std::io::Error),
+    /// Couldn't reach the host
+    Connect(std::io::Error),
+    /// The other side closed the connection
+    Disconnected,
+    /// Any other socket error, the connection is unusable after this
+    Io(std::io::Error),
+    /// Received something that isn't a chess_networking packet
+    BadPacket,
+    /// Received a valid packet at a point where the protocol doesn't allow it
+    UnexpectedPacket,
+    Encode,
+    /// Recording the traffic was asked for but the capture file couldn't be created
+    Capture(std::io::Error),
+    /// The lobby server turned down what we asked for
+    Refused(String),
+    /// The host wants a game password and ours was wrong or missing
+    WrongPassword,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Bind(e) => write!(f, "Could not host game: {}", e),
+            NetworkError::Connect(e) => write!(f, "Could not connect to host: {}", e),
+            NetworkError::Disconnected => write!(f, "Opponent disconnected"),
+            NetworkError::Io(e) => write!(f, "Connection lost: {}", e),
+            NetworkError::BadPacket => write!(f, "Opponent sent an invalid packet"),
+            NetworkError::UnexpectedPacket => write!(f, "Opponent sent an unexpected packet"),
+            NetworkError::Encode => write!(f, "Failed to encode packet"),
+            NetworkError::Capture(e) => write!(f, "Could not start recording traffic: {}", e),
+            NetworkError::Refused(reason) => write!(f, "Lobby: {}", reason),
+            NetworkError::WrongPassword => write!(f, "Wrong game password"),
+        }
+    }
+}
+
+impl From<FrameError> for NetworkError {
+    fn from(_: FrameError) -> Self {
+        NetworkError::BadPacket
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof
+            | ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            // what a read fails with once keepalive gives up on the connection
+            | ErrorKind::TimedOut => NetworkError::Disconnected,
+            _ => NetworkError::Io(e),
+        }
+    }
+}
+
+/// Receive buffer that splits the incoming byte stream into whole packets. chess_networking
+/// packets are MessagePack values, which carry their own lengths, so a frame ends where the
+/// top level value ends.
+#[derive(Default)]
+pub(crate) struct MessageBuffer {
+    buf: Vec<u8>,
+}
+
+impl MessageBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Removes and returns the next complete frame, `Ok(None)` if more bytes are needed
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        match frame_len(&self.buf)? {
+            Some(len) => Ok(Some(self.buf.drain(..len).collect())),
+            None if self.buf.len() > MAX_FRAME_LEN => Err(FrameError::TooLarge),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes and returns everything buffered, the start of a frame that's still arriving too
+    pub fn take_rest(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    /// Like `next_frame` but decodes the frame
+    pub fn next_message(&mut self) -> Result<Option<Message>, NetworkError> {
+        match self.next_frame()? {
+            Some(frame) => Message::decode(&frame)
+                .map(Some)
+                .ok_or(NetworkError::BadPacket),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Length of the MessagePack value at the start of `buf`, `None` if it isn't complete yet
+fn frame_len(buf: &[u8]) -> Result<Option<usize>, FrameError> {
+    // reads a big endian length field of `size` bytes at `pos`
+    let read_len = |pos: usize, size: usize| -> Option<usize> {
+        let bytes = buf.get(pos..pos + size)?;
+        Some(bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize))
+    };
+
+    let mut pos = 0;
+    // number of values we still have to skip past, arrays and maps add their elements
+    let mut remaining: usize = 1;
+
+    while remaining > 0 {
+        remaining -= 1;
+
+        let Some(&marker) = buf.get(pos) else {
+            return Ok(None);
+        };
+        pos += 1;
+
+        // (bytes to skip after the marker, nested values that follow)
+        let (skip, values) = match marker {
+            0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => (0, 0),
+            0x80..=0x8f => (0, (marker & 0x0f) as usize * 2),
+            0x90..=0x9f => (0, (marker & 0x0f) as usize),
+            0xa0..=0xbf => ((marker & 0x1f) as usize, 0),
+            0xc4 | 0xd9 => match read_len(pos, 1) {
+                Some(len) => (1 + len, 0),
+                None => return Ok(None),
+            },
+            0xc5 | 0xda => match read_len(pos, 2) {
+                Some(len) => (2 + len, 0),
+                None => return Ok(None),
+            },
+            0xc6 | 0xdb => match read_len(pos, 4) {
+                Some(len) => (4 + len, 0),
+                None => return Ok(None),
+            },
+            // ext: length, type byte, data
+            0xc7 => match read_len(pos, 1) {
+                Some(len) => (2 + len, 0),
+                None => return Ok(None),
+            },
+            0xc8 => match read_len(pos, 2) {
+                Some(len) => (3 + len, 0),
+                None => return Ok(None),
+            },
+            0xc9 => match read_len(pos, 4) {
+                Some(len) => (5 + len, 0),
+                None => return Ok(None),
+            },
+            0xcc | 0xd0 => (1, 0),
+            0xcd | 0xd1 => (2, 0),
+            0xca | 0xce | 0xd2 => (4, 0),
+            0xcb | 0xcf | 0xd3 => (8, 0),
+            // fixext: type byte plus 1, 2, 4, 8 or 16 bytes of data
+            0xd4..=0xd8 => (1 + (1 << (marker - 0xd4)), 0),
+            0xdc => match read_len(pos, 2) {
+                Some(len) => (2, len),
+                None => return Ok(None),
+            },
+            0xdd => match read_len(pos, 4) {
+                Some(len) => (4, len),
+                None => return Ok(None),
+            },
+            0xde => match read_len(pos, 2) {
+                Some(len) => (2, len * 2),
+                None => return Ok(None),
+            },
+            0xdf => match read_len(pos, 4) {
+                Some(len) => (4, len * 2),
+                None => return Ok(None),
+            },
+            0xc1 => return Err(FrameError::InvalidMarker(marker)),
+        };
+
+        pos += skip;
+        remaining += values;
+
+        if pos > MAX_FRAME_LEN || remaining > MAX_FRAME_LEN {
+            return Err(FrameError::TooLarge);
+        }
+    }
+
+    if pos > buf.len() {
+        Ok(None)
+    } else {
+        Ok(Some(pos))
+    }
+}
+
+/// Carries chess_networking messages to and from the opponent. The game only talks to the
+/// opponent through this, so it doesn't care whether there is a socket behind it.
+pub(crate) trait Transport: Send + Sync {
+    /// Returns the next complete message if one has arrived, never blocks
+    fn read(&mut self) -> Result<Option<Message>, NetworkError>;
+
+    fn write(&mut self, message: Message) -> Result<(), NetworkError>;
+
+    /// Keeps reading until a message arrives, returns `None` if `cancel` gets set first.
+    /// Only meant to be called off the main thread.
+    fn read_blocking(&mut self, cancel: &AtomicBool) -> Result<Option<Message>, NetworkError> {
+        while !cancel.load(Ordering::Relaxed) {
+            if let Some(message) = self.read()? {
+                return Ok(Some(message));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        Ok(None)
+    }
+}
+
+/// A tcp connection to the opponent
 pub(crate) struct Connection {
-    pub stream: TcpStream,
+    stream: TcpStream,
+    buffer: MessageBuffer,
+    /// Set once we've read EOF, buffered messages are still handed out after that
+    closed: bool,
 }
 
 impl Connection {
-    pub fn new_server(address: &str) -> Self {
-        let listener = TcpListener::bind(address).expect("Failed to bind address");
+    pub fn from_stream(stream: TcpStream) -> Result<Self, NetworkError> {
+        stream.set_nonblocking(true)?;
+        SockRef::from(&stream).set_tcp_keepalive(&keepalive())?;
 
-        listener
-            .set_nonblocking(true)
-            .expect("Failed to set nonblocking to true");
+        Ok(Connection {
+            stream,
+            buffer: MessageBuffer::default(),
+            closed: false,
+        })
+    }
 
-        loop {
+    /// Waits for a client to connect, returns `None` if `cancel` gets set before that happens.
+    pub fn new_server(address: &str, cancel: &AtomicBool) -> Result<Option<Self>, NetworkError> {
+        Connection::accept(&Connection::listen(address)?, cancel)
+    }
+
+    /// Listens on the host address, for `accept`
+    pub fn listen(address: &str) -> Result<TcpListener, NetworkError> {
+        let listener = TcpListener::bind(address).map_err(NetworkError::Bind)?;
+        listener.set_nonblocking(true).map_err(NetworkError::Bind)?;
+        Ok(listener)
+    }
+
+    /// Waits for the next client on `listener`, returns `None` if `cancel` gets set first
+    pub fn accept(
+        listener: &TcpListener,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Self>, NetworkError> {
+        while !cancel.load(Ordering::Relaxed) {
             match listener.accept() {
                 Ok((stream, address)) => {
                     println!("Client connected: {}", address);
-
-                    stream
-                        .set_nonblocking(true)
-                        .expect("Failed to set nonblocking to true");
-                    return Connection { stream };
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    println!("Waiting for client to connect...");
-                    std::thread::sleep(Duration::from_secs(1));
+                    return Connection::from_stream(stream).map(Some);
                 }
-                Err(e) => {
-                    panic!("Error in Connection::new_server(): {}", e);
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(100));
                 }
+                Err(e) => return Err(NetworkError::Io(e)),
             }
         }
+
+        Ok(None)
+    }
+
+    pub fn new_client(address: &str) -> Result<Self, NetworkError> {
+        Connection::connect(address, DEFAULT_PORT)
     }
 
-    pub fn new_client(address: &str) -> Self {
-        let stream = TcpStream::connect(address).expect("Failed to connect to host");
+    /// Connects to what the player typed in, `default_port` is used if it doesn't say. Each
+    /// address it resolves to is tried for at most `CONNECT_TIMEOUT`.
+    pub fn connect(address: &str, default_port: u16) -> Result<Self, NetworkError> {
+        let addresses = resolve_address(address, default_port).map_err(NetworkError::Connect)?;
 
-        stream
-            .set_nonblocking(true)
-            .expect("Failed to set non-blocking to true");
+        let mut last_error = None;
+        for address in &addresses {
+            match TcpStream::connect_timeout(address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Connection::from_stream(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
 
-        Connection { stream }
+        Err(NetworkError::Connect(last_error.unwrap_or_else(|| {
+            std::io::Error::new(ErrorKind::NotFound, "address resolved to nothing")
+        })))
     }
+}
+
+fn keepalive() -> TcpKeepalive {
+    let keepalive = TcpKeepalive::new().with_time(KEEPALIVE_TIME);
+
+    // the rest isn't configurable everywhere, the os defaults are a lot slower but still work
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    let keepalive = keepalive.with_interval(KEEPALIVE_INTERVAL);
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    let keepalive = keepalive.with_retries(KEEPALIVE_RETRIES);
+
+    keepalive
+}
 
-    pub fn read(&mut self) -> Vec<u8> {
+impl Transport for Connection {
+    /// Moves everything that has arrived on the socket into the receive buffer and returns the
+    /// next complete message, if there is one
+    fn read(&mut self) -> Result<Option<Message>, NetworkError> {
         let mut buf = [0u8; 2048];
 
-        match self.stream.read(&mut buf) {
-            Ok(len) => buf[0..len].to_vec(),
-            Err(_) => Vec::new(),
+        while !self.closed {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(len) => self.buffer.push(&buf[0..len]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        match self.buffer.next_message()? {
+            Some(message) => Ok(Some(message)),
+            None if self.closed => Err(NetworkError::Disconnected),
+            None => Ok(None),
         }
     }
 
-    pub fn write(&mut self, buf: Vec<u8>) {
-        self.stream
-            .write_all(&buf)
-            .expect("Failed to write to stream");
+    fn write(&mut self, message: Message) -> Result<(), NetworkError> {
+        let buf = message.encode()?;
+        self.stream.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+type ByteQueue = Arc<Mutex<VecDeque<Vec<u8>>>>;
+
+/// One end of an in-process connection, for tests and for playing against yourself. Messages
+/// are still encoded and go through a `MessageBuffer`, so both ends see exactly what they
+/// would over tcp. Dropping one end disconnects the other.
+pub(crate) struct Loopback {
+    incoming: ByteQueue,
+    outgoing: ByteQueue,
+    buffer: MessageBuffer,
+}
+
+impl Loopback {
+    /// Two ends connected to each other
+    pub fn pair() -> (Loopback, Loopback) {
+        let a: ByteQueue = Arc::default();
+        let b: ByteQueue = Arc::default();
+
+        (
+            Loopback {
+                incoming: a.clone(),
+                outgoing: b.clone(),
+                buffer: MessageBuffer::default(),
+            },
+            Loopback {
+                incoming: b,
+                outgoing: a,
+                buffer: MessageBuffer::default(),
+            },
+        )
+    }
+
+    /// The other end holds the only other reference to each queue
+    fn peer_dropped(&self) -> bool {
+        Arc::strong_count(&self.outgoing) == 1
+    }
+}
+
+impl Transport for Loopback {
+    fn read(&mut self) -> Result<Option<Message>, NetworkError> {
+        let received: Vec<Vec<u8>> = self
+            .incoming
+            .lock()
+            .map_err(|_| NetworkError::Disconnected)?
+            .drain(..)
+            .collect();
+
+        for bytes in received {
+            self.buffer.push(&bytes);
+        }
+
+        match self.buffer.next_message()? {
+            Some(message) => Ok(Some(message)),
+            None if self.peer_dropped() => Err(NetworkError::Disconnected),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&mut self, message: Message) -> Result<(), NetworkError> {
+        if self.peer_dropped() {
+            return Err(NetworkError::Disconnected);
+        }
+
+        let buf = message.encode()?;
+        self.outgoing
+            .lock()
+            .map_err(|_| NetworkError::Disconnected)?
+            .push_back(buf);
+        Ok(())
+    }
+}
+
+/// Result of a finished connection attempt. `start` is the packet sent by the server, it
+/// decides the starting position and which color the server plays.
+pub(crate) struct Handshake {
+    pub connection: Box<dyn Transport>,
+    pub start: chess_networking::Start,
+    pub opponent_name: Option<String>,
+    /// What the opponent listed in its `Start`, empty for clients without our extensions
+    pub peer_extensions: Vec<String>,
+    /// What the client presents to resume the game after the connection broke, `None` if the
+    /// other side doesn't support resuming
+    pub resume_token: Option<String>,
+}
+
+/// Getting a connection, or anything else off the network, on a thread of its own so neither
+/// the app nor bevy's task pools wait on the network. Dropping this cancels it.
+pub(crate) struct ConnectThread<T = Handshake> {
+    result: Mutex<Receiver<Result<Option<T>, NetworkError>>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> ConnectThread<T> {
+    /// Runs `connect` with the flag it should stop at once it's set
+    pub fn spawn(
+        connect: impl FnOnce(&AtomicBool) -> Result<Option<T>, NetworkError> + Send + 'static,
+    ) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, result) = mpsc::channel();
+
+        let thread_cancel = cancel.clone();
+        std::thread::spawn(move || {
+            // nobody listens anymore after a cancel
+            let _ = sender.send(connect(&thread_cancel));
+        });
+
+        ConnectThread {
+            result: Mutex::new(result),
+            cancel,
+        }
+    }
+
+    /// What the thread came back with, `None` while it's still at it. A thread that went away
+    /// without an answer counts as cancelled.
+    pub fn poll(&self) -> Option<Result<Option<T>, NetworkError>> {
+        match self.result.lock().unwrap().try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Ok(None)),
+        }
+    }
+}
+
+impl<T> Drop for ConnectThread<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Connects (or waits for a connection) and exchanges `Start` packets. This blocks, so it is
+/// run on a `ConnectThread` while the connecting screen is shown. `Ok(None)` means we were
+/// cancelled. With a `capture` path all traffic, the handshake included, is recorded there.
+/// Hosting keeps listening for the right player, clients without the `password` or failing the
+/// handshake some other way are turned away.
+pub(crate) fn establish(
+    role: NetworkRole,
+    address: &str,
+    name: &str,
+    settings: &HostSettings,
+    password: Option<&str>,
+    capture: Option<&Path>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    if role == NetworkRole::Client {
+        let mut connection: Box<dyn Transport> = Box::new(Connection::new_client(address)?);
+        if let Some(path) = capture {
+            connection = record(connection, path, role)?;
+        }
+        return handshake(role, connection, name, settings, password, cancel);
+    }
+
+    let listener = Connection::listen(address)?;
+    loop {
+        let mut connection: Box<dyn Transport> = match Connection::accept(&listener, cancel)? {
+            Some(connection) => Box::new(connection),
+            None => return Ok(None),
+        };
+
+        if let Some(path) = capture {
+            connection = record(connection, path, role)?;
+        }
+
+        // a stranger misbehaving is no reason to stop waiting
+        match handshake(role, connection, name, settings, password, cancel) {
+            Err(NetworkError::WrongPassword) => {
+                println!("Turned away a client with the wrong password");
+            }
+            Err(e) => println!("Turned away a client: {}", e),
+            result => return result,
+        }
+    }
+}
+
+pub(crate) fn record(
+    connection: Box<dyn Transport>,
+    path: &Path,
+    role: NetworkRole,
+) -> Result<Box<dyn Transport>, NetworkError> {
+    match Capture::new(connection, path, role) {
+        Ok(capture) => {
+            println!("Recording traffic to {}", path.display());
+            Ok(Box::new(capture))
+        }
+        Err(e) => Err(NetworkError::Capture(e)),
+    }
+}
+
+/// Waits for the opponent of an interrupted game to connect again. Anyone else is turned away,
+/// the returning opponent is answered with `start` describing where the game stopped. An
+/// opponent that was handed a `resume_token` has to present it, one without resume support is
+/// known by its name and can't come back anonymously.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn await_reconnect(
+    address: &str,
+    start: chess_networking::Start,
+    opponent_name: Option<String>,
+    resume_token: Option<String>,
+    password: Option<&str>,
+    capture: Option<&Path>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    let listener = Connection::listen(address)?;
+    loop {
+        let mut connection: Box<dyn Transport> = match Connection::accept(&listener, cancel)? {
+            Some(connection) => Box::new(connection),
+            None => return Ok(None),
+        };
+
+        if let Some(path) = capture {
+            connection = record(connection, path, NetworkRole::Server)?;
+        }
+
+        // a stranger misbehaving is no reason to stop waiting
+        let hello = match read_client_start(connection.as_mut(), password, cancel) {
+            Ok(Some(hello)) => hello,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                println!("Turned away a client: {}", e);
+                continue;
+            }
+        };
+
+        let returning = match &resume_token {
+            Some(token) => hello.resume_token.as_ref() == Some(token),
+            None => hello.packet.name.is_some() && hello.packet.name == opponent_name,
+        };
+        if !returning {
+            println!(
+                "Turned away {}, waiting for {}",
+                hello.packet.name.as_deref().unwrap_or("anonymous"),
+                opponent_name.as_deref().unwrap_or("anonymous")
+            );
+            continue;
+        }
+
+        connection.write(Message::start(start.clone()))?;
+        let resume_token =
+            send_resume_token(connection.as_mut(), &hello.extensions, resume_token.clone())?;
+
+        return Ok(Some(Handshake {
+            connection,
+            start,
+            opponent_name: hello.packet.name,
+            peer_extensions: hello.extensions,
+            resume_token,
+        }));
+    }
+}
+
+/// Connects to the host of an interrupted game again, presenting the `resume_token` it handed
+/// out so the host knows it's us
+pub(crate) fn rejoin(
+    address: &str,
+    name: &str,
+    resume_token: Option<&str>,
+    password: Option<&str>,
+    capture: Option<&Path>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    let mut connection: Box<dyn Transport> = Box::new(Connection::new_client(address)?);
+    if let Some(path) = capture {
+        connection = record(connection, path, NetworkRole::Client)?;
+    }
+
+    client_handshake(connection, name, password, resume_token, cancel)
+}
+
+/// What the client opens a game with, the server decides everything else
+pub(crate) fn client_start(name: &str) -> chess_networking::Start {
+    chess_networking::Start {
+        is_white: false,
+        name: Some(name.to_string()),
+        fen: None,
+        time: None,
+        inc: None,
+    }
+}
+
+/// Rejects a server's `Start` that a game can't be set up from
+pub(crate) fn check_start(start: &chess_networking::Start) -> Result<(), NetworkError> {
+    match &start.fen {
+        Some(fen) if Position::from_fen(fen).is_err() => Err(NetworkError::BadPacket),
+        _ => Ok(()),
+    }
+}
+
+/// A new resume token, hard enough to guess for telling a returning player from a stranger.
+/// `RandomState` is seeded by the os, the time keeps tokens apart even if that's weak.
+fn new_resume_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    let [a, b] = [0u8, 1].map(|half| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(half);
+        hasher.write_u128(nanos);
+        hasher.finish()
+    });
+    format!("{:016x}{:016x}", a, b)
+}
+
+/// Hands a client that supports resuming the token it can come back with, `token` or a new one
+fn send_resume_token(
+    connection: &mut dyn Transport,
+    peer_extensions: &[String],
+    token: Option<String>,
+) -> Result<Option<String>, NetworkError> {
+    if !peer_extensions.iter().any(|name| name == extension::RESUME) {
+        return Ok(None);
+    }
+
+    let token = token.unwrap_or_else(new_resume_token);
+    connection.write(Message::Extension(Extension::ResumeToken(token.clone())))?;
+    Ok(Some(token))
+}
+
+/// What a client sends to open a game
+struct ClientHello {
+    packet: chess_networking::Start,
+    extensions: Vec<String>,
+    /// Presented by a client coming back to an interrupted game
+    resume_token: Option<String>,
+}
+
+/// Reads the client's `Start` and the password and resume token sent before it. A client that
+/// doesn't know `password` is told so if it understands our extensions, either way it's an
+/// error.
+fn read_client_start(
+    connection: &mut dyn Transport,
+    password: Option<&str>,
+    cancel: &AtomicBool,
+) -> Result<Option<ClientHello>, NetworkError> {
+    let mut presented = None;
+    let mut resume_token = None;
+    let (packet, extensions) = loop {
+        match connection.read_blocking(cancel)? {
+            Some(Message::Extension(Extension::Password(password))) if presented.is_none() => {
+                presented = Some(password);
+            }
+            Some(Message::Extension(Extension::Resume(token))) if resume_token.is_none() => {
+                resume_token = Some(token);
+            }
+            Some(Message::Start(packet, extensions)) => break (packet, extensions),
+            Some(_) => return Err(NetworkError::UnexpectedPacket),
+            None => return Ok(None),
+        }
+    };
+
+    if password.is_some_and(|password| presented.as_deref() != Some(password)) {
+        if !extensions.is_empty() {
+            connection.write(Message::Extension(Extension::PasswordRejected {}))?;
+        }
+        return Err(NetworkError::WrongPassword);
+    }
+
+    Ok(Some(ClientHello {
+        packet,
+        extensions,
+        resume_token,
+    }))
+}
+
+/// Exchanges `Start` packets over a fresh connection. The client speaks first, the server
+/// answers with the packet that decides the game. The client sends `password` ahead of its
+/// `Start`, the server requires it.
+pub(crate) fn handshake(
+    role: NetworkRole,
+    mut connection: Box<dyn Transport>,
+    name: &str,
+    settings: &HostSettings,
+    password: Option<&str>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    match role {
+        NetworkRole::Server => {
+            let Some(hello) = read_client_start(connection.as_mut(), password, cancel)? else {
+                return Ok(None);
+            };
+
+            println!(
+                "Client with name {} connected",
+                hello.packet.name.as_deref().unwrap_or("client")
+            );
+
+            let response_packet = settings.start_packet(name);
+            connection.write(Message::start(response_packet.clone()))?;
+            let resume_token = send_resume_token(connection.as_mut(), &hello.extensions, None)?;
+
+            Ok(Some(Handshake {
+                connection,
+                start: response_packet,
+                opponent_name: hello.packet.name,
+                peer_extensions: hello.extensions,
+                resume_token,
+            }))
+        }
+        NetworkRole::Client => client_handshake(connection, name, password, None, cancel),
+    }
+}
+
+/// The client's side of `handshake`, a returning client presents its `resume_token` too
+fn client_handshake(
+    mut connection: Box<dyn Transport>,
+    name: &str,
+    password: Option<&str>,
+    resume_token: Option<&str>,
+    cancel: &AtomicBool,
+) -> Result<Option<Handshake>, NetworkError> {
+    // only sent when asked for, other hosts wouldn't understand them
+    if let Some(password) = password {
+        connection.write(Message::Extension(Extension::Password(
+            password.to_string(),
+        )))?;
+    }
+    if let Some(token) = resume_token {
+        connection.write(Message::Extension(Extension::Resume(token.to_string())))?;
+    }
+    connection.write(Message::start(client_start(name)))?;
+
+    // wait for start packet from server
+    let (packet, peer_extensions) = match connection.read_blocking(cancel)? {
+        Some(Message::Start(packet, extensions)) => (packet, extensions),
+        Some(Message::Extension(Extension::PasswordRejected {})) => {
+            return Err(NetworkError::WrongPassword)
+        }
+        Some(_) => return Err(NetworkError::UnexpectedPacket),
+        None => return Ok(None),
+    };
+
+    check_start(&packet)?;
+
+    // a host that supports resuming follows up with our token
+    let resume_token = if peer_extensions.iter().any(|name| name == extension::RESUME) {
+        match connection.read_blocking(cancel)? {
+            Some(Message::Extension(Extension::ResumeToken(token))) => Some(token),
+            Some(_) => return Err(NetworkError::UnexpectedPacket),
+            None => return Ok(None),
+        }
+    } else {
+        None
+    };
+
+    Ok(Some(Handshake {
+        connection,
+        opponent_name: packet.name.clone(),
+        start: packet,
+        peer_extensions,
+        resume_token,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_bytes() -> Vec<u8> {
+        chess_networking::Start {
+            is_white: true,
+            name: Some("Servermannen".to_string()),
+            fen: Some("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()),
+            time: None,
+            inc: None,
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    fn move_bytes() -> Vec<u8> {
+        chess_networking::Move {
+            from: (4, 1),
+            to: (4, 3),
+            promotion: None,
+            forfeit: false,
+            offer_draw: false,
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    fn ack_bytes() -> Vec<u8> {
+        chess_networking::Ack {
+            ok: true,
+            end_state: None,
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    /// A local address nothing listens on yet, for hosting over tcp
+    fn free_address() -> String {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn resolves_address_literals() {
+        let resolve = |input| resolve_address(input, DEFAULT_PORT).unwrap();
+
+        assert_eq!(resolve(""), vec!["127.0.0.1:22022".parse().unwrap()]);
+        assert_eq!(resolve("10.0.0.2"), vec!["10.0.0.2:22022".parse().unwrap()]);
+        assert_eq!(
+            resolve("10.0.0.2:1234"),
+            vec!["10.0.0.2:1234".parse().unwrap()]
+        );
+        assert_eq!(resolve("::1"), vec!["[::1]:22022".parse().unwrap()]);
+        assert_eq!(resolve("[::1]"), vec!["[::1]:22022".parse().unwrap()]);
+        assert_eq!(resolve("[::1]:1234"), vec!["[::1]:1234".parse().unwrap()]);
+    }
+
+    #[test]
+    fn spectators_use_the_next_port() {
+        assert_eq!(spectator_address(""), "127.0.0.1:22023");
+        assert_eq!(spectator_address("10.0.0.2:1234"), "10.0.0.2:1235");
+        assert_eq!(spectator_address("[::1]"), "[::1]:22023");
+    }
+
+    #[test]
+    fn frame_len_of_nested_values() {
+        // [ "a", 256, {} ]
+        let buf = [0x93, 0xa1, b'a', 0xcd, 0x01, 0x00, 0x80];
+        assert_eq!(frame_len(&buf), Ok(Some(buf.len())));
+
+        for end in 0..buf.len() {
+            assert_eq!(frame_len(&buf[..end]), Ok(None));
+        }
+    }
+
+    #[test]
+    fn invalid_marker_is_an_error() {
+        let mut buffer = MessageBuffer::default();
+        buffer.push(&[0xc1, 0x00]);
+        assert_eq!(buffer.next_frame(), Err(FrameError::InvalidMarker(0xc1)));
+    }
+
+    #[test]
+    fn fragmented_packet_is_reassembled() {
+        let mut buffer = MessageBuffer::default();
+        let bytes = move_bytes();
+
+        for byte in &bytes[..bytes.len() - 1] {
+            buffer.push(&[*byte]);
+            assert!(buffer.next_message().unwrap().is_none());
+        }
+        buffer.push(&bytes[bytes.len() - 1..]);
+
+        match buffer.next_message().unwrap() {
+            Some(Message::Move(packet)) => {
+                assert_eq!(packet.from, (4, 1));
+                assert_eq!(packet.to, (4, 3));
+            }
+            other => panic!("expected move, got {:?}", other),
+        }
+        assert!(buffer.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn concatenated_packets_are_split() {
+        let mut buffer = MessageBuffer::default();
+        buffer.push(&[start_bytes(), move_bytes(), ack_bytes()].concat());
+
+        assert!(
+            matches!(buffer.next_message().unwrap(), Some(Message::Start(packet, _)) if packet.is_white)
+        );
+        assert!(matches!(
+            buffer.next_message().unwrap(),
+            Some(Message::Move(_))
+        ));
+        assert!(matches!(buffer.next_message().unwrap(), Some(Message::Ack(packet)) if packet.ok));
+        assert!(buffer.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn extensions_ride_along_in_start() {
+        let packet = chess_networking::Start::try_from(start_bytes().as_slice()).unwrap();
+        let ours = Message::start(packet).encode().unwrap();
+
+        let mut buffer = MessageBuffer::default();
+        buffer.push(&[ours, start_bytes()].concat());
+
+        assert!(matches!(
+            buffer.next_message().unwrap(),
+            Some(Message::Start(packet, extensions))
+                if packet.is_white && extensions == extension::supported()
+        ));
+        assert!(matches!(
+            buffer.next_message().unwrap(),
+            Some(Message::Start(_, extensions)) if extensions.is_empty()
+        ));
+    }
+
+    #[test]
+    fn extensions_are_added_after_any_map_header() {
+        let fixmap = start_bytes();
+        assert!((0x80..=0x8e).contains(&fixmap[0]));
+        let len = fixmap[0] & 0x0f;
+
+        // the same start with its entries counted in a map16 header
+        let mut map16 = [&[0xde, 0x00, len][..], &fixmap[1..]].concat();
+        extension::add_to_start(&mut map16, extension::supported()).unwrap();
+        assert_eq!(map16[..3], [0xde, 0x00, len + 1]);
+
+        // a full fixmap grows into a map16
+        let mut full = [&[0x8f][..], &fixmap[1..]].concat();
+        extension::add_to_start(&mut full, extension::supported()).unwrap();
+        assert_eq!(full[..3], [0xde, 0x00, 0x10]);
+
+        assert!(matches!(
+            Message::decode(&map16),
+            Some(Message::Start(packet, extensions))
+                if packet.is_white && extensions == extension::supported()
+        ));
+
+        // anything that isn't a map is refused instead of sent without them
+        let mut array = vec![0x92, 0x01, 0x02];
+        assert!(matches!(
+            extension::add_to_start(&mut array, extension::supported()),
+            Err(NetworkError::Encode)
+        ));
+        assert_eq!(array, [0x92, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn password_rejected_is_a_single_key_map() {
+        let buf = Message::Extension(Extension::PasswordRejected {})
+            .encode()
+            .unwrap();
+        assert_eq!(buf[0], 0x81);
+        assert!(matches!(
+            Message::decode(&buf),
+            Some(Message::Extension(Extension::PasswordRejected {}))
+        ));
+    }
+
+    #[test]
+    fn chat_is_not_mistaken_for_a_packet() {
+        let chat = Message::Extension(Extension::Chat("good luck".to_string()));
+        let bytes = chat.encode().unwrap();
+
+        assert!(matches!(
+            Message::decode(&bytes),
+            Some(Message::Extension(Extension::Chat(text))) if text == "good luck"
+        ));
+    }
+
+    #[test]
+    fn unknown_extensions_are_named_not_rejected() {
+        // { "Takeback": 1 }, from a client that knows more than we do
+        let mut bytes = vec![0x81, 0xa8];
+        bytes.extend_from_slice(b"Takeback");
+        bytes.push(0x01);
+
+        assert!(matches!(
+            Message::decode(&bytes),
+            Some(Message::Extension(Extension::Unknown(name))) if name == "Takeback"
+        ));
+        // anything else that isn't a packet still is an error
+        assert!(Message::decode(&[0x82, 0xa1, b'a', 0x01, 0xa1, b'b', 0x02]).is_none());
+    }
+
+    #[test]
+    fn rest_keeps_a_partial_frame() {
+        let ack = ack_bytes();
+        let mut buffer = MessageBuffer::default();
+        buffer.push(&[&ack[..], &ack[..2]].concat());
+
+        assert_eq!(buffer.next_frame().unwrap(), Some(ack.clone()));
+        assert_eq!(buffer.take_rest(), ack[..2]);
+        assert_eq!(buffer.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn packets_split_at_arbitrary_boundaries() {
+        let stream = [ack_bytes(), move_bytes(), ack_bytes(), start_bytes()].concat();
+
+        for chunk_size in 1..stream.len() {
+            let mut buffer = MessageBuffer::default();
+            let mut messages = Vec::new();
+
+            for chunk in stream.chunks(chunk_size) {
+                buffer.push(chunk);
+                while let Some(message) = buffer.next_message().unwrap() {
+                    messages.push(message);
+                }
+            }
+
+            assert_eq!(messages.len(), 4, "chunk size {}", chunk_size);
+            assert!(matches!(messages[0], Message::Ack(_)));
+            assert!(matches!(messages[1], Message::Move(_)));
+            assert!(matches!(messages[2], Message::Ack(_)));
+            assert!(matches!(messages[3], Message::Start(..)));
+        }
+    }
+
+    #[test]
+    fn loopback_delivers_in_order_until_dropped() {
+        let (mut a, mut b) = Loopback::pair();
+
+        assert!(matches!(b.read(), Ok(None)));
+
+        a.write(Message::decode(&move_bytes()).unwrap()).unwrap();
+        a.write(Message::decode(&ack_bytes()).unwrap()).unwrap();
+
+        assert!(matches!(b.read(), Ok(Some(Message::Move(_)))));
+        assert!(matches!(b.read(), Ok(Some(Message::Ack(_)))));
+        assert!(matches!(b.read(), Ok(None)));
+
+        // whatever was sent before the drop is still delivered
+        b.write(Message::decode(&ack_bytes()).unwrap()).unwrap();
+        drop(b);
+
+        assert!(matches!(a.read(), Ok(Some(Message::Ack(_)))));
+        assert!(matches!(a.read(), Err(NetworkError::Disconnected)));
+        assert!(matches!(
+            a.write(Message::decode(&ack_bytes()).unwrap()),
+            Err(NetworkError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn host_keeps_listening_after_a_bad_handshake() {
+        let address = free_address();
+
+        let listen_address = address.clone();
+        let host = std::thread::spawn(move || {
+            establish(
+                NetworkRole::Server,
+                &listen_address,
+                "Servermannen",
+                &HostSettings::default(),
+                None,
+                None,
+                &AtomicBool::new(false),
+            )
+        });
+
+        // wait until the host listens
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        let mut stranger = loop {
+            match TcpStream::connect(&address) {
+                Ok(stream) => break stream,
+                Err(_) => assert!(std::time::Instant::now() < deadline, "host never listened"),
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        // not msgpack at all
+        stranger.write_all(&[0xc1]).unwrap();
+        drop(stranger);
+
+        let client = establish(
+            NetworkRole::Client,
+            &address,
+            "Klientmannen",
+            &HostSettings::default(),
+            None,
+            None,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        .unwrap();
+        let host = host.join().unwrap().unwrap().unwrap();
+
+        assert_eq!(client.opponent_name.as_deref(), Some("Servermannen"));
+        assert_eq!(host.opponent_name.as_deref(), Some("Klientmannen"));
+    }
+
+    /// Waits for the opponent of a game on its own thread, `cancel` stops it
+    fn await_on_thread(
+        address: &str,
+        opponent_name: Option<&str>,
+        resume_token: Option<&str>,
+        cancel: &Arc<AtomicBool>,
+    ) -> std::thread::JoinHandle<Result<Option<Handshake>, NetworkError>> {
+        let address = address.to_string();
+        let opponent_name = opponent_name.map(str::to_string);
+        let resume_token = resume_token.map(str::to_string);
+        let cancel = cancel.clone();
+        std::thread::spawn(move || {
+            let start = HostSettings::default().start_packet("Servermannen");
+            await_reconnect(
+                &address,
+                start,
+                opponent_name,
+                resume_token,
+                None,
+                None,
+                &cancel,
+            )
+        })
+    }
+
+    /// Tries to come back until the host listens, the host's answer if it let us in
+    fn rejoin_when_listening(
+        address: &str,
+        name: &str,
+        resume_token: Option<&str>,
+    ) -> Result<Option<Handshake>, NetworkError> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        loop {
+            match rejoin(
+                address,
+                name,
+                resume_token,
+                None,
+                None,
+                &AtomicBool::new(false),
+            ) {
+                Err(NetworkError::Connect(_)) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_hands_out_a_resume_token() {
+        let (host_connection, client_connection) = Loopback::pair();
+        let client = std::thread::spawn(move || {
+            handshake(
+                NetworkRole::Client,
+                Box::new(client_connection),
+                "Klientmannen",
+                &HostSettings::default(),
+                None,
+                &AtomicBool::new(false),
+            )
+        });
+        let host = handshake(
+            NetworkRole::Server,
+            Box::new(host_connection),
+            "Servermannen",
+            &HostSettings::default(),
+            None,
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        .unwrap();
+        let client = client.join().unwrap().unwrap().unwrap();
+
+        assert!(host.resume_token.is_some());
+        assert_eq!(host.resume_token, client.resume_token);
+    }
+
+    #[test]
+    fn only_the_resume_token_brings_the_opponent_back() {
+        let address = free_address();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let host = await_on_thread(&address, Some("Klientmannen"), Some("secret"), &cancel);
+
+        // the right name isn't enough
+        assert!(rejoin_when_listening(&address, "Klientmannen", Some("guess")).is_err());
+        assert!(rejoin_when_listening(&address, "Klientmannen", None).is_err());
+
+        let client = rejoin_when_listening(&address, "Klientmannen", Some("secret"))
+            .unwrap()
+            .unwrap();
+        let host = host.join().unwrap().unwrap().unwrap();
+        assert_eq!(host.resume_token.as_deref(), Some("secret"));
+        assert_eq!(client.resume_token.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn anonymous_opponents_need_a_token_to_come_back() {
+        let address = free_address();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let host = await_on_thread(&address, None, None, &cancel);
+
+        // anyone could claim to be nobody
+        let mut connection = loop {
+            match Connection::connect(&address, DEFAULT_PORT) {
+                Ok(connection) => break connection,
+                Err(_) => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        connection
+            .write(Message::Start(
+                chess_networking::Start {
+                    name: None,
+                    ..client_start("")
+                },
+                Vec::new(),
+            ))
+            .unwrap();
+        assert!(connection.read_blocking(&AtomicBool::new(false)).is_err());
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(host.join().unwrap(), Ok(None)));
+    }
+
+    #[test]
+    fn dropping_the_connect_thread_stops_listening() {
+        let address = free_address();
+
+        let listen_address = address.clone();
+        let connect: ConnectThread = ConnectThread::spawn(move |cancel| {
+            Connection::new_server(&listen_address, cancel)?;
+            Ok(None)
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(connect.poll().is_none());
+        drop(connect);
+
+        // the port is free again once the thread noticed
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        while TcpListener::bind(&address).is_err() {
+            assert!(std::time::Instant::now() < deadline, "still listening");
+            std::thread::sleep(Duration::from_millis(50));
+        }
     }
 }
@@ -0,0 +1,558 @@
+//! Runs the game's side of the protocol against scripted opponents from `mock_peer`, over a
+//! loopback instead of tcp. The handshake, receiving and own moves go through the same
+//! functions the systems use, only the clicking is skipped.
+
+use std::{
+    path::Path,
+    sync::atomic::AtomicBool,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use vhultman_chess::{Color as PieceColor, PieceType};
+
+use crate::{
+    general::resources::{HostColor, HostSettings, NetworkRole},
+    mock_peer::{MockError, Script},
+};
+
+use super::{
+    capture::Capture,
+    networking::{handshake, Handshake, Loopback, Message, NetworkError, Transport},
+    systems::board::receive_packets,
+    ClientGameState, EndReason, GameResult, NetworkState,
+};
+
+/// The game with the ui taken away
+struct Gui {
+    game_state: ClientGameState,
+    connection: Box<dyn Transport>,
+    opponent_name: Option<String>,
+}
+
+impl Gui {
+    /// Plays a move like `handle_picking` and `promotion_menu_action` do
+    fn play(&mut self, from: &str, to: &str, promotion: Option<PieceType>) {
+        let mut m = self
+            .game_state
+            .board_state
+            .get_move(square(from), square(to))
+            .expect("test move should be legal");
+
+        if let Some(promotion) = promotion {
+            m.set_promotion_piece(promotion);
+        }
+
+        let packet = self.game_state.play_own_move(m);
+        self.connection.write(Message::Move(packet)).unwrap();
+    }
+
+    /// Receives like `wait_for_move` every frame until `done` returns true
+    fn poll_until(
+        &mut self,
+        done: impl Fn(&mut ClientGameState) -> bool,
+    ) -> Result<(), NetworkError> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while Instant::now() < deadline {
+            receive_packets(&mut self.game_state, self.connection.as_mut())?;
+            if done(&mut self.game_state) {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        panic!("timed out waiting for the game state");
+    }
+}
+
+/// Starts the mock on its own thread and does the handshake against it
+fn connect(
+    role: NetworkRole,
+    settings: &HostSettings,
+    script: &str,
+) -> (Result<Gui, NetworkError>, JoinHandle<Result<(), MockError>>) {
+    connect_to_script(role, settings, Script::parse(script).unwrap(), None)
+}
+
+/// Like `connect`, optionally recording the game's traffic to `capture`
+fn connect_to_script(
+    role: NetworkRole,
+    settings: &HostSettings,
+    script: Script,
+    capture: Option<&Path>,
+) -> (Result<Gui, NetworkError>, JoinHandle<Result<(), MockError>>) {
+    let (connection, mut mock_connection) = Loopback::pair();
+
+    let mock = std::thread::spawn(move || script.run(&mut mock_connection));
+
+    let mut connection: Box<dyn Transport> = Box::new(connection);
+    if let Some(path) = capture {
+        connection = Box::new(Capture::new(connection, path, role).unwrap());
+    }
+
+    let gui = handshake(
+        role,
+        connection,
+        "Gui",
+        settings,
+        None,
+        &AtomicBool::new(false),
+    )
+    .map(|handshake| {
+        let Handshake {
+            connection,
+            start,
+            opponent_name,
+            ..
+        } = handshake.expect("handshake isn't cancelled");
+
+        Gui {
+            game_state: ClientGameState::from_start(&start, role).unwrap(),
+            connection,
+            opponent_name,
+        }
+    });
+
+    (gui, mock)
+}
+
+fn finish(mock: JoinHandle<Result<(), MockError>>) {
+    if let Err(e) = mock.join().unwrap() {
+        panic!("mock peer failed: {}", e);
+    }
+}
+
+fn square(name: &str) -> u32 {
+    let name = name.as_bytes();
+    let x = (name[0] - b'a') as u32;
+    let y = (name[1] - b'1') as u32;
+    (7 - y) * 8 + x
+}
+
+fn host_settings(color: HostColor, fen: Option<&str>) -> HostSettings {
+    HostSettings {
+        color,
+        fen: fen.map(|fen| fen.to_string()),
+        ..HostSettings::default()
+    }
+}
+
+#[test]
+fn client_receives_start_and_acks_move() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white name=Mock
+        send move e2 e4
+        expect ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    assert_eq!(gui.opponent_name.as_deref(), Some("Mock"));
+    assert!(gui.game_state.own_color == PieceColor::Black);
+    assert_eq!(gui.game_state.network_state, NetworkState::AwaitingMove);
+
+    gui.poll_until(|game_state| game_state.network_state == NetworkState::Normal)
+        .unwrap();
+    let pawn = gui.game_state.board_state.piece_on(square("e4")).unwrap();
+    assert!(pawn.t == PieceType::Pawn && pawn.color == PieceColor::White);
+
+    finish(mock);
+}
+
+#[test]
+fn server_answers_start_and_plays() {
+    let (gui, mock) = connect(
+        NetworkRole::Server,
+        &host_settings(HostColor::White, None),
+        "
+        send start name=Mock
+        expect start white
+        expect move e2 e4
+        send ack ok
+        send move e7 e5
+        expect ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    assert!(gui.game_state.own_color == PieceColor::White);
+    assert_eq!(gui.game_state.network_state, NetworkState::Normal);
+
+    gui.play("e2", "e4", None);
+    assert_eq!(gui.game_state.network_state, NetworkState::AwaitingAck);
+
+    gui.poll_until(|game_state| game_state.network_state == NetworkState::Normal)
+        .unwrap();
+    let pawn = gui.game_state.board_state.piece_on(square("e5")).unwrap();
+    assert!(pawn.t == PieceType::Pawn && pawn.color == PieceColor::Black);
+
+    finish(mock);
+}
+
+#[test]
+fn start_fen_and_time_control_are_used() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start black time=300 inc=5 fen=7k/8/8/8/8/8/8/K6R w - - 0 1
+        ",
+    );
+    let gui = gui.unwrap();
+
+    assert!(gui.game_state.own_color == PieceColor::White);
+    assert_eq!(gui.game_state.network_state, NetworkState::Normal);
+    assert!(gui.game_state.board_state.piece_on(square("e2")).is_none());
+
+    let clock = gui.game_state.clock.as_ref().unwrap();
+    assert_eq!(clock.remaining(PieceColor::White), Duration::from_secs(300));
+    assert_eq!(clock.remaining(PieceColor::Black), Duration::from_secs(300));
+
+    finish(mock);
+}
+
+#[test]
+fn invalid_start_fen_is_rejected() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white fen=not a fen
+        ",
+    );
+
+    assert!(matches!(gui, Err(NetworkError::BadPacket)));
+
+    finish(mock);
+}
+
+#[test]
+fn received_promotion_is_applied() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white fen=7k/P7/8/8/8/8/8/K7 w - - 0 1
+        send move a7 a8 promote=n
+        expect ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    gui.poll_until(|game_state| game_state.network_state == NetworkState::Normal)
+        .unwrap();
+    let knight = gui.game_state.board_state.piece_on(square("a8")).unwrap();
+    assert!(knight.t == PieceType::Knight && knight.color == PieceColor::White);
+
+    finish(mock);
+}
+
+#[test]
+fn own_promotion_is_sent() {
+    let (gui, mock) = connect(
+        NetworkRole::Server,
+        &host_settings(HostColor::White, Some("7k/P7/8/8/8/8/8/K7 w - - 0 1")),
+        "
+        send start name=Mock
+        expect start white
+        expect move a7 a8 promote=r
+        send ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    gui.play("a7", "a8", Some(PieceType::Rook));
+    gui.poll_until(|game_state| game_state.network_state == NetworkState::AwaitingMove)
+        .unwrap();
+
+    finish(mock);
+}
+
+#[test]
+fn illegal_move_is_nacked() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white
+        send move e2 e5
+        expect ack nack
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    // the illegal move isn't played and ends the game, the host wins even though it made it
+    gui.poll_until(|game_state| game_state.is_game_over())
+        .unwrap();
+    assert!(gui.game_state.board_state.piece_on(square("e5")).is_none());
+    assert_eq!(
+        gui.game_state.result,
+        Some(GameResult::win(PieceColor::White, EndReason::IllegalMove))
+    );
+
+    finish(mock);
+}
+
+#[test]
+fn nacked_move_ends_the_game() {
+    let (gui, mock) = connect(
+        NetworkRole::Server,
+        &host_settings(HostColor::White, None),
+        "
+        send start
+        expect start white
+        expect move e2 e4
+        send ack nack
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    gui.play("e2", "e4", None);
+    gui.poll_until(|game_state| game_state.is_game_over())
+        .unwrap();
+
+    // the gui is the server, so the rejected move makes it win
+    assert_eq!(
+        gui.game_state.result,
+        Some(GameResult::win(PieceColor::White, EndReason::IllegalMove))
+    );
+
+    finish(mock);
+}
+
+#[test]
+fn checkmate_is_reported_in_ack() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        include_str!("../../mock_scripts/fools_mate_as_host.txt"),
+    );
+    let mut gui = gui.unwrap();
+
+    gui.play("f2", "f3", None);
+    gui.poll_until(|game_state| game_state.network_state == NetworkState::Normal)
+        .unwrap();
+    gui.play("g2", "g4", None);
+    gui.poll_until(|game_state| game_state.is_game_over())
+        .unwrap();
+
+    assert_eq!(
+        gui.game_state.result,
+        Some(GameResult::win(PieceColor::Black, EndReason::Checkmate))
+    );
+
+    finish(mock);
+}
+
+#[test]
+fn forfeit_ends_the_game() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white
+        send forfeit
+        expect ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    gui.poll_until(|game_state| game_state.is_game_over())
+        .unwrap();
+    assert_eq!(
+        gui.game_state.result,
+        Some(GameResult::win(PieceColor::Black, EndReason::Forfeit))
+    );
+
+    finish(mock);
+}
+
+#[test]
+fn draw_offer_ack_waits_for_answer() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white
+        send move e2 e4 draw
+        expect ack ok end=draw
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    gui.poll_until(|game_state| game_state.incoming_draw_offer)
+        .unwrap();
+
+    // accepting, like draw_offer_action
+    gui.game_state.result = Some(GameResult::draw(EndReason::Agreement));
+    gui.game_state.incoming_draw_offer = false;
+    gui.game_state.network_state = NetworkState::Normal;
+    gui.connection
+        .write(Message::Ack(chess_networking::Ack {
+            ok: true,
+            end_state: Some(chess_networking::GameState::Draw),
+        }))
+        .unwrap();
+
+    finish(mock);
+}
+
+#[test]
+fn unexpected_packet_is_an_error() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white
+        send ack ok
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    assert!(matches!(
+        gui.poll_until(|_| false),
+        Err(NetworkError::UnexpectedPacket)
+    ));
+
+    finish(mock);
+}
+
+#[test]
+fn disconnect_is_detected() {
+    let (gui, mock) = connect(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        "
+        expect start
+        send start white
+        close
+        ",
+    );
+    let mut gui = gui.unwrap();
+
+    finish(mock);
+    assert!(matches!(
+        gui.poll_until(|_| false),
+        Err(NetworkError::Disconnected)
+    ));
+}
+
+#[test]
+fn capture_replays_the_same_game() {
+    let path = std::env::temp_dir().join(format!("viering_chess_{}.capture", std::process::id()));
+    let play = |gui: &mut Gui| {
+        gui.play("f2", "f3", None);
+        gui.poll_until(|game_state| game_state.network_state == NetworkState::Normal)
+            .unwrap();
+        gui.play("g2", "g4", None);
+        gui.poll_until(|game_state| game_state.is_game_over())
+            .unwrap();
+    };
+
+    // record a game against the script
+    let script = Script::parse(include_str!("../../mock_scripts/fools_mate_as_host.txt")).unwrap();
+    let (gui, mock) = connect_to_script(
+        NetworkRole::Client,
+        &HostSettings::default(),
+        script,
+        Some(&path),
+    );
+    play(&mut gui.unwrap());
+    finish(mock);
+
+    // and play it again against the capture
+    let source = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let (script, role) = Script::from_capture(&source).unwrap();
+    assert!(role == NetworkRole::Server);
+
+    let (gui, mock) =
+        connect_to_script(NetworkRole::Client, &HostSettings::default(), script, None);
+    play(&mut gui.unwrap());
+    finish(mock);
+}
+
+/// Runs the server's and the client's handshake against each other with the given passwords
+fn handshake_with_passwords(
+    required: Option<&'static str>,
+    presented: Option<&'static str>,
+) -> (
+    Result<Option<Handshake>, NetworkError>,
+    Result<Option<Handshake>, NetworkError>,
+) {
+    let (server_connection, client_connection) = Loopback::pair();
+
+    let client = std::thread::spawn(move || {
+        handshake(
+            NetworkRole::Client,
+            Box::new(client_connection),
+            "Klient",
+            &HostSettings::default(),
+            presented,
+            &AtomicBool::new(false),
+        )
+    });
+    let server = handshake(
+        NetworkRole::Server,
+        Box::new(server_connection),
+        "Server",
+        &HostSettings::default(),
+        required,
+        &AtomicBool::new(false),
+    );
+
+    (server, client.join().unwrap())
+}
+
+#[test]
+fn right_password_completes_the_handshake() {
+    let (server, client) = handshake_with_passwords(Some("swordfish"), Some("swordfish"));
+
+    assert!(
+        matches!(server, Ok(Some(handshake)) if handshake.opponent_name.as_deref() == Some("Klient"))
+    );
+    assert!(
+        matches!(client, Ok(Some(handshake)) if handshake.opponent_name.as_deref() == Some("Server"))
+    );
+}
+
+#[test]
+fn wrong_or_missing_password_is_rejected() {
+    for presented in [Some("hunter2"), None] {
+        let (server, client) = handshake_with_passwords(Some("swordfish"), presented);
+
+        assert!(matches!(server, Err(NetworkError::WrongPassword)));
+        assert!(matches!(client, Err(NetworkError::WrongPassword)));
+    }
+}
+
+#[test]
+fn clients_without_password_support_are_turned_away() {
+    let (connection, mut mock_connection) = Loopback::pair();
+    let script = Script::parse("send start name=Mock").unwrap();
+    let mock = std::thread::spawn(move || script.run(&mut mock_connection));
+
+    let result = handshake(
+        NetworkRole::Server,
+        Box::new(connection),
+        "Gui",
+        &HostSettings::default(),
+        Some("swordfish"),
+        &AtomicBool::new(false),
+    );
+    assert!(matches!(result, Err(NetworkError::WrongPassword)));
+
+    finish(mock);
+}
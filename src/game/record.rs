@@ -0,0 +1,101 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use vhultman_chess::Color as PieceColor;
+
+use crate::general::resources::NetworkHandler;
+
+use super::resources::ClientGameState;
+use super::utils::position_to_fen;
+
+/// Game records and saved positions go here
+pub(crate) const SAVE_DIR: &str = "saved_games";
+
+/// The game as text: who played, where it started, the moves, how it ended and the chat
+pub(crate) fn game_record(
+    game_state: &ClientGameState,
+    network_handler: &NetworkHandler,
+) -> String {
+    let own_name = network_handler.display_name();
+    let opponent_name = network_handler
+        .opponent_name
+        .clone()
+        .unwrap_or("anonymous".to_string());
+
+    let mut record = String::new();
+    if network_handler.spectating {
+        let _ = writeln!(record, "Watched: {}", opponent_name);
+    } else {
+        let (white, black) = match game_state.own_color {
+            PieceColor::White => (&own_name, &opponent_name),
+            PieceColor::Black => (&opponent_name, &own_name),
+        };
+        let _ = writeln!(record, "White: {}", white);
+        let _ = writeln!(record, "Black: {}", black);
+    }
+
+    if let Some(fen) = &game_state.start.fen {
+        let _ = writeln!(record, "Start: {}", fen);
+    }
+    let _ = writeln!(
+        record,
+        "Result: {}",
+        game_state
+            .result
+            .map_or("unfinished".to_string(), |result| result.to_string())
+    );
+    if game_state.result.is_none() {
+        let _ = writeln!(
+            record,
+            "Position: {}",
+            position_to_fen(&game_state.board_state, game_state.last_move)
+        );
+    }
+
+    record.push('\n');
+    for line in game_state.numbered_moves() {
+        let _ = writeln!(record, "{}", line);
+    }
+
+    if !game_state.chat.is_empty() {
+        record.push_str("\nChat:\n");
+        for line in &game_state.chat {
+            let name = if line.own { &own_name } else { &opponent_name };
+            let _ = writeln!(record, "{}: {}", name, line.text);
+        }
+    }
+
+    record
+}
+
+/// Writes the record of a game that got anywhere, named after the time it's saved
+pub(crate) fn save_game_record(
+    game_state: &ClientGameState,
+    network_handler: &NetworkHandler,
+) -> Option<std::io::Result<PathBuf>> {
+    if game_state.history.is_empty() && game_state.chat.is_empty() {
+        return None;
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let path = Path::new(SAVE_DIR).join(format!("game_{}.txt", timestamp));
+
+    let result = std::fs::create_dir_all(SAVE_DIR)
+        .and_then(|_| std::fs::write(&path, game_record(game_state, network_handler)))
+        .map(|_| path);
+    Some(result)
+}
+
+/// Logs where `save_game_record` put the record
+pub(crate) fn report_saved(saved: Option<std::io::Result<PathBuf>>) {
+    match saved {
+        Some(Ok(path)) => println!("Saved the game to {}", path.display()),
+        Some(Err(e)) => println!("Failed to save the game: {}", e),
+        None => {}
+    }
+}
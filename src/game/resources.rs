@@ -1,3 +1,6 @@
+use std::fmt;
+use std::time::Duration;
+
 use bevy::{
     asset::Handle,
     pbr::StandardMaterial,
@@ -5,8 +8,16 @@ use bevy::{
 };
 use vhultman_chess::ChessMove;
 use vhultman_chess::Color as PieceColor;
+use vhultman_chess::GameState;
+use vhultman_chess::PieceType;
 use vhultman_chess::Position;
 
+use crate::general::resources::NetworkRole;
+
+use super::extension::Extension;
+use super::networking::{NetworkError, Transport, ACK_TIMEOUT};
+use super::utils::{color_name, move_name, move_to_packet, other_color, position_to_fen};
+
 #[derive(Resource)]
 pub struct PieceModelData {
     pub pawn_parts: Vec<Handle<Mesh>>,
@@ -26,6 +37,95 @@ pub struct SquareResourceData {
     pub selected_square: Handle<StandardMaterial>,
 }
 
+/// Starting time and increment per move, both in seconds like in the `Start` packet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub time: u64,
+    pub inc: u64,
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{}", self.time / 60, self.inc)
+    }
+}
+
+/// How hard the built-in computer plays, see `computer`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Difficulty {
+    Beginner,
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+#[derive(Clone)]
+pub struct ChessClock {
+    white: Duration,
+    black: Duration,
+    increment: Duration,
+    /// The side whose time is running
+    running: PieceColor,
+}
+
+impl ChessClock {
+    pub fn new(time_control: TimeControl, side_to_move: PieceColor) -> Self {
+        ChessClock {
+            white: Duration::from_secs(time_control.time),
+            black: Duration::from_secs(time_control.time),
+            increment: Duration::from_secs(time_control.inc),
+            running: side_to_move,
+        }
+    }
+
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        match color {
+            PieceColor::White => self.white,
+            PieceColor::Black => self.black,
+        }
+    }
+
+    pub fn set_remaining(&mut self, color: PieceColor, remaining: Duration) {
+        *self.time_mut(color) = remaining;
+    }
+
+    pub fn increment(&self) -> Duration {
+        self.increment
+    }
+
+    /// Runs the clock of `side` for `delta`. When the side changes the player that just moved
+    /// gets their increment.
+    pub fn tick(&mut self, side: PieceColor, delta: Duration) {
+        if side != self.running {
+            let increment = self.increment;
+            *self.time_mut(self.running) += increment;
+            self.running = side;
+        }
+
+        let time = self.time_mut(side);
+        *time = time.saturating_sub(delta);
+    }
+
+    /// The player that ran out of time, if any
+    pub fn flagged(&self) -> Option<PieceColor> {
+        if self.white.is_zero() {
+            Some(PieceColor::White)
+        } else if self.black.is_zero() {
+            Some(PieceColor::Black)
+        } else {
+            None
+        }
+    }
+
+    fn time_mut(&mut self, color: PieceColor) -> &mut Duration {
+        match color {
+            PieceColor::White => &mut self.white,
+            PieceColor::Black => &mut self.black,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum NetworkState {
     Normal,
@@ -33,9 +133,99 @@ pub enum NetworkState {
     AwaitingAck,
 }
 
+/// Why a game ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EndReason {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    InsufficientMaterial,
+    /// A move was rejected. The server wins no matter who made it.
+    IllegalMove,
+    Forfeit,
+    Timeout,
+    /// The opponent went away mid-game
+    Disconnect,
+    /// The opponent stopped answering and the win was claimed
+    Unresponsive,
+    /// A draw offer was accepted
+    Agreement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameResult {
+    /// `None` for a draw
+    pub winner: Option<PieceColor>,
+    pub reason: EndReason,
+}
+
+impl GameResult {
+    pub fn win(winner: PieceColor, reason: EndReason) -> Self {
+        GameResult {
+            winner: Some(winner),
+            reason,
+        }
+    }
+
+    pub fn draw(reason: EndReason) -> Self {
+        GameResult {
+            winner: None,
+            reason,
+        }
+    }
+
+    pub fn loser(&self) -> Option<PieceColor> {
+        self.winner.map(other_color)
+    }
+
+    /// The end state told to the opponent in an ack. Only results decided by the move itself or
+    /// by agreement are sent, the rest are known to both sides already.
+    pub fn end_state(&self) -> Option<chess_networking::GameState> {
+        match self.reason {
+            EndReason::Checkmate => Some(chess_networking::GameState::CheckMate),
+            EndReason::Stalemate
+            | EndReason::Repetition
+            | EndReason::InsufficientMaterial
+            | EndReason::Agreement => Some(chess_networking::GameState::Draw),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(winner), Some(loser)) = (self.winner, self.loser()) else {
+            return match self.reason {
+                EndReason::Stalemate => write!(f, "Draw by stalemate"),
+                EndReason::Repetition => write!(f, "Draw by repetition"),
+                EndReason::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+                _ => write!(f, "Draw by agreement"),
+            };
+        };
+
+        let (winner, loser) = (color_name(winner), color_name(loser));
+        match self.reason {
+            EndReason::IllegalMove => {
+                write!(f, "Illegal move, {} wins as the host", winner)
+            }
+            EndReason::Forfeit => write!(f, "{} resigned, {} wins", loser, winner),
+            EndReason::Timeout => write!(f, "{} ran out of time, {} wins", loser, winner),
+            EndReason::Disconnect => write!(f, "{} disconnected, {} wins", loser, winner),
+            EndReason::Unresponsive => {
+                write!(f, "{} stopped responding, {} wins", loser, winner)
+            }
+            _ => write!(f, "Checkmate, {} wins", winner),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct ClientGameState {
+    /// The `Start` packet the game was set up from
+    pub start: chess_networking::Start,
     pub board_state: Position,
+    /// Every move played since the start, ours and the opponent's
+    pub history: Vec<ChessMove>,
     pub selected_piece: Option<u32>,
     pub spawned_pieces: u32,
     pub board_dirty: bool,
@@ -43,5 +233,533 @@ pub struct ClientGameState {
     pub pending_promotion_move: Option<ChessMove>,
     pub own_color: PieceColor,
     pub network_state: NetworkState,
-    pub next_ack_state: Option<chess_networking::GameState>,
+    /// The color played by the server, who wins when a move is rejected
+    pub server_color: PieceColor,
+    /// Set once the game has ended, however it ended
+    pub result: Option<GameResult>,
+    /// Offer a draw along with our next move
+    pub offer_draw: bool,
+    /// The opponent offered a draw with their last move, its ack is held back until we answer
+    pub incoming_draw_offer: bool,
+    /// Our last move offered a draw, the opponent holds back its ack until they answer
+    pub outgoing_draw_offer: bool,
+    /// `None` when playing without a time control
+    pub clock: Option<ChessClock>,
+    /// How long we've been waiting on the opponent since we last heard from them
+    pub opponent_silence: Duration,
+    /// The player wants to play again once the game is over
+    pub rematch_requested: bool,
+    /// `Start` for the next game, received after this one ended. From the client it asks for a
+    /// rematch, from the host it starts one.
+    pub rematch_start: Option<chess_networking::Start>,
+    /// Everything said in the chat, oldest first
+    pub chat: Vec<ChatLine>,
+    /// Capabilities the opponent sent that haven't been moved into `PeerCapabilities` yet
+    pub announced_capabilities: Option<PeerCapabilities>,
+    /// A takeback waiting for an answer, no moves are played until it has one
+    pub takeback: Option<Takeback>,
+    /// How often moves were taken back, spectators get the game from the start when it changes
+    pub takebacks: u32,
+}
+
+/// A takeback one of the players asked for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Takeback {
+    /// Half moves to take back
+    pub plies: u32,
+    /// Moves played when it was asked for, it only applies to that position
+    pub moves: u32,
+    /// Asked for by the opponent rather than us
+    pub incoming: bool,
+}
+
+/// What the opponent's client supports on top of chess_networking. Until its capabilities
+/// arrive it's what it listed in its `Start`, clients of other teams list nothing and are only
+/// ever sent chess_networking packets.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct PeerCapabilities {
+    /// `None` until the opponent's capabilities arrived
+    pub version: Option<u32>,
+    pub features: Vec<String>,
+}
+
+impl PeerCapabilities {
+    pub fn from_start(extensions: Vec<String>) -> Self {
+        PeerCapabilities {
+            version: None,
+            features: extensions,
+        }
+    }
+
+    /// Whether the opponent understands our extension messages at all
+    pub fn any(&self) -> bool {
+        !self.features.is_empty()
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|supported| supported == feature)
+    }
+}
+
+/// A line of the chat, the names are filled in when it's shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatLine {
+    /// Typed by us rather than the opponent
+    pub own: bool,
+    pub text: String,
+}
+
+impl ClientGameState {
+    /// A new game as described by the server's `Start` packet, seen from `role`'s side. Fails
+    /// if the packet's position can't be set up.
+    pub fn from_start(
+        start: &chess_networking::Start,
+        role: NetworkRole,
+    ) -> Result<Self, NetworkError> {
+        let board_state = start_position(start)?;
+
+        let clock = start.time.map(|time| {
+            ChessClock::new(
+                TimeControl {
+                    time,
+                    inc: start.inc.unwrap_or(0),
+                },
+                board_state.current_side(),
+            )
+        });
+
+        // is_white is from the server's point of view
+        let own_color = if start.is_white == (role == NetworkRole::Server) {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        };
+
+        let network_state = if board_state.current_side() == own_color {
+            NetworkState::Normal
+        } else {
+            NetworkState::AwaitingMove
+        };
+
+        Ok(ClientGameState {
+            start: start.clone(),
+            board_state,
+            history: Vec::new(),
+            board_dirty: true,
+            last_move: None,
+            pending_promotion_move: None,
+            selected_piece: None,
+            spawned_pieces: 0,
+            own_color,
+            network_state,
+            server_color: if start.is_white {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            },
+            result: None,
+            offer_draw: false,
+            incoming_draw_offer: false,
+            outgoing_draw_offer: false,
+            clock,
+            opponent_silence: Duration::ZERO,
+            rematch_requested: false,
+            rematch_start: None,
+            chat: Vec::new(),
+            announced_capabilities: None,
+            takeback: None,
+            takebacks: 0,
+        })
+    }
+
+    /// The moves so far numbered like on a score sheet, one full move per line
+    pub fn numbered_moves(&self) -> Vec<String> {
+        // the start position may have black to move
+        let current_side = self.board_state.current_side();
+        let black_first = if self.history.len() % 2 == 0 {
+            current_side == PieceColor::Black
+        } else {
+            current_side == PieceColor::White
+        };
+
+        let mut names = self.history.iter().map(|m| move_name(*m));
+        let mut lines = Vec::new();
+        if black_first && !self.history.is_empty() {
+            lines.push(format!("1. ... {}", names.next().unwrap_or_default()));
+        }
+        let mut names = names.peekable();
+        while names.peek().is_some() {
+            let white = names.next().unwrap_or_default();
+            let black = names.next().unwrap_or_default();
+            let line = format!("{}. {} {}", lines.len() + 1, white, black);
+            lines.push(line.trim_end().to_string());
+        }
+
+        lines
+    }
+
+    /// A local game where both players move from the same window, see `HotSeat`
+    pub fn hot_seat(start: &chess_networking::Start) -> Result<Self, NetworkError> {
+        let mut game_state = Self::from_start(start, NetworkRole::Server)?;
+        game_state.pass_turn();
+        Ok(game_state)
+    }
+
+    /// Hands the board to the side to move in a hot-seat game, nobody has to ack our moves
+    pub fn pass_turn(&mut self) {
+        self.own_color = self.board_state.current_side();
+        self.network_state = NetworkState::Normal;
+    }
+
+    /// Plays one of our own moves and returns the packet telling the opponent about it
+    pub fn play_own_move(&mut self, m: ChessMove) -> chess_networking::Move {
+        self.board_state.make_move(m);
+        self.history.push(m);
+        self.network_state = NetworkState::AwaitingAck;
+        self.last_move = Some(m);
+        self.board_dirty = true;
+        self.pending_promotion_move = None;
+        self.opponent_silence = Duration::ZERO;
+        self.update_result_from_board();
+
+        self.outgoing_draw_offer = std::mem::take(&mut self.offer_draw);
+        chess_networking::Move {
+            offer_draw: self.outgoing_draw_offer,
+            ..move_to_packet(m)
+        }
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Ends the game if the last move left the board in checkmate or a draw
+    pub fn update_result_from_board(&mut self) {
+        if self.result.is_some() {
+            return;
+        }
+
+        // the side to move is the one that got mated
+        let mover = other_color(self.board_state.current_side());
+        self.result = match self.board_state.check_game_state() {
+            GameState::Playing => None,
+            GameState::Checkmate => Some(GameResult::win(mover, EndReason::Checkmate)),
+            GameState::Stalemate => Some(GameResult::draw(EndReason::Stalemate)),
+            GameState::DrawByRepetition => Some(GameResult::draw(EndReason::Repetition)),
+            GameState::DrawByInsufficientMaterial => {
+                Some(GameResult::draw(EndReason::InsufficientMaterial))
+            }
+        };
+    }
+
+    /// The `Start` packet the host answers a reconnecting opponent with. It only has room for
+    /// one time, so it carries the opponent's remaining time and they keep their own idea of ours.
+    pub fn resume_packet(&self, name: &str) -> chess_networking::Start {
+        let opponent_color = other_color(self.own_color);
+
+        chess_networking::Start {
+            is_white: self.own_color == PieceColor::White,
+            name: Some(name.to_string()),
+            fen: Some(position_to_fen(&self.board_state, self.last_move)),
+            // rounded up so a player with time left doesn't lose on the resync
+            time: self
+                .clock
+                .as_ref()
+                .map(|clock| clock.remaining(opponent_color).as_millis().div_ceil(1000) as u64),
+            inc: self.clock.as_ref().map(|clock| clock.increment().as_secs()),
+        }
+    }
+
+    /// Continues the game over a new connection. The client takes the position and its time from
+    /// `start`, the host sent it and already has both. Whatever was in flight when the connection
+    /// broke is forgotten, the side to move in the position decides who plays next.
+    pub fn resume(
+        &mut self,
+        start: &chess_networking::Start,
+        role: NetworkRole,
+    ) -> Result<(), NetworkError> {
+        if role == NetworkRole::Client {
+            let resumed = ClientGameState::from_start(start, role)?;
+
+            // the highlighted move is only right if we were already in the same position
+            if position_to_fen(&resumed.board_state, None)
+                != position_to_fen(&self.board_state, None)
+            {
+                self.last_move = None;
+            }
+            // the move list, game records, spectators and takebacks go by the moves
+            self.resync_history(start, &resumed.board_state);
+            self.board_state = resumed.board_state;
+            self.own_color = resumed.own_color;
+            self.server_color = resumed.server_color;
+            self.board_dirty = true;
+
+            match (self.clock.as_mut(), start.time) {
+                (Some(clock), Some(time)) => {
+                    clock.set_remaining(self.own_color, Duration::from_secs(time))
+                }
+                _ => self.clock = resumed.clock,
+            }
+        }
+
+        self.network_state = if self.board_state.current_side() == self.own_color {
+            NetworkState::Normal
+        } else {
+            NetworkState::AwaitingMove
+        };
+        self.selected_piece = None;
+        self.pending_promotion_move = None;
+        self.offer_draw = false;
+        self.incoming_draw_offer = false;
+        self.outgoing_draw_offer = false;
+        self.opponent_silence = Duration::ZERO;
+        // the opponent never heard of it or won't hear the answer
+        self.takeback = None;
+        Ok(())
+    }
+
+    /// Makes `history` lead to the host's `position`. Only one move can have been lost in
+    /// flight, ours or the host's, if that doesn't explain it the game starts over from the
+    /// position in the host's `start`.
+    fn resync_history(&mut self, start: &chess_networking::Start, position: &Position) {
+        let fen = position_to_fen(position, None);
+        let replay = |moves: &[ChessMove]| {
+            let mut board_state = start_position(&self.start).ok()?;
+            for m in moves {
+                board_state.make_move(*m);
+            }
+            Some(position_to_fen(&board_state, None))
+        };
+
+        if position_to_fen(&self.board_state, None) == fen {
+            return;
+        }
+
+        // our last move never reached the host
+        if let Some(kept) = self.history.len().checked_sub(1) {
+            if replay(&self.history[..kept]).as_ref() == Some(&fen) {
+                self.history.truncate(kept);
+                return;
+            }
+        }
+
+        // the host's last move never reached us
+        let side = self.board_state.current_side();
+        let mut candidates = Vec::new();
+        for square in 0..64 {
+            if !self
+                .board_state
+                .piece_on(square)
+                .is_some_and(|piece| piece.color == side)
+            {
+                continue;
+            }
+
+            for m in self.board_state.moves_for_square(square).iter().copied() {
+                if !m.is_promotion() {
+                    candidates.push(m);
+                    continue;
+                }
+
+                for piece in [
+                    PieceType::Queen,
+                    PieceType::Rook,
+                    PieceType::Bishop,
+                    PieceType::Knight,
+                ] {
+                    let mut m = m;
+                    m.set_promotion_piece(piece);
+                    candidates.push(m);
+                }
+            }
+        }
+
+        let missed = candidates.into_iter().find(|m| {
+            let mut moves = self.history.clone();
+            moves.push(*m);
+            replay(&moves).as_ref() == Some(&fen)
+        });
+        match missed {
+            Some(m) => self.history.push(m),
+            None => {
+                self.start.fen = start.fen.clone();
+                self.history.clear();
+            }
+        }
+    }
+
+    /// How many half moves taking back our last move takes, `None` while it can't be asked for
+    pub fn takeback_plies(&self) -> Option<u32> {
+        if self.is_game_over()
+            || self.takeback.is_some()
+            || self.pending_promotion_move.is_some()
+            || self.incoming_draw_offer
+        {
+            return None;
+        }
+
+        let plies = match self.network_state {
+            // our move was the last one
+            NetworkState::AwaitingMove => 1,
+            // the opponent answered it already
+            NetworkState::Normal => 2,
+            // the opponent may not have our move yet
+            NetworkState::AwaitingAck => return None,
+        };
+        (self.history.len() >= plies).then_some(plies as u32)
+    }
+
+    /// Asks to take back our last move, returns the request to send
+    pub fn request_takeback(&mut self) -> Option<Extension> {
+        let plies = self.takeback_plies()?;
+        let moves = self.history.len() as u32;
+        self.takeback = Some(Takeback {
+            plies,
+            moves,
+            incoming: false,
+        });
+
+        Some(Extension::Takeback { plies, moves })
+    }
+
+    /// Holds on to the opponent's request until the player answers it. One that can't be
+    /// granted, because a move crossed it or we asked at the same time, is declined right away.
+    pub fn receive_takeback(&mut self, plies: u32, moves: u32) -> Option<Extension> {
+        let applies = self.takeback.is_none()
+            && !self.is_game_over()
+            && self.network_state != NetworkState::AwaitingAck
+            && moves as usize == self.history.len()
+            && (1..=2).contains(&plies)
+            && plies <= moves;
+
+        if !applies {
+            return Some(Extension::TakebackAnswer(false));
+        }
+
+        self.takeback = Some(Takeback {
+            plies,
+            moves,
+            incoming: true,
+        });
+        None
+    }
+
+    /// Answers the opponent's request, returns the answer to send
+    pub fn answer_takeback(&mut self, accept: bool) -> Option<Extension> {
+        let takeback = self.takeback.filter(|takeback| takeback.incoming)?;
+        self.takeback = None;
+
+        let accepted = accept
+            && takeback.moves as usize == self.history.len()
+            && self.network_state != NetworkState::AwaitingAck
+            && self.take_back(takeback.plies);
+        Some(Extension::TakebackAnswer(accepted))
+    }
+
+    /// The opponent answered our request. Our moves were on hold, so it still applies.
+    pub fn receive_takeback_answer(&mut self, accepted: bool) -> Result<(), NetworkError> {
+        match self.takeback {
+            Some(takeback) if !takeback.incoming => {
+                self.takeback = None;
+                if accepted && !self.is_game_over() {
+                    self.take_back(takeback.plies);
+                }
+                Ok(())
+            }
+            _ => Err(NetworkError::UnexpectedPacket),
+        }
+    }
+
+    /// Rolls the game back `plies` half moves by playing the rest again from the start. The
+    /// board is resynced from the position, so captured pieces come back.
+    pub fn take_back(&mut self, plies: u32) -> bool {
+        let plies = plies as usize;
+        if plies == 0 || plies > self.history.len() {
+            return false;
+        }
+
+        let kept = self.history.len() - plies;
+        let Ok(mut board_state) = start_position(&self.start) else {
+            return false;
+        };
+        for m in &self.history[..kept] {
+            board_state.make_move(*m);
+        }
+
+        self.board_state = board_state;
+        self.history.truncate(kept);
+        // without a last move update_board only compares the pieces to the position
+        self.last_move = None;
+        self.board_dirty = true;
+        self.selected_piece = None;
+        self.pending_promotion_move = None;
+        self.offer_draw = false;
+        self.takebacks += 1;
+        self.network_state = if self.board_state.current_side() == self.own_color {
+            NetworkState::Normal
+        } else {
+            NetworkState::AwaitingMove
+        };
+        true
+    }
+
+    /// How long the opponent may stay silent before they're considered unresponsive, `None`
+    /// while we don't expect anything from them. Only a missing ack counts, a move or an answer
+    /// to a draw offer may take as long as the player wants and a dead connection is noticed by
+    /// keepalive.
+    pub fn response_timeout(&self) -> Option<Duration> {
+        if self.is_game_over() || self.incoming_draw_offer || self.outgoing_draw_offer {
+            return None;
+        }
+
+        (self.network_state == NetworkState::AwaitingAck).then_some(ACK_TIMEOUT)
+    }
+
+    /// The opponent kept us waiting too long, the player may claim the win or keep waiting
+    pub fn opponent_unresponsive(&self) -> bool {
+        self.response_timeout()
+            .is_some_and(|timeout| self.opponent_silence >= timeout)
+    }
+
+    /// Ends the game in our favour and hangs up, there's no packet for it so the opponent
+    /// learns the game is over from the closed connection
+    pub fn claim_win(&mut self, connection: &mut Option<Box<dyn Transport>>) {
+        self.result = Some(GameResult::win(self.own_color, EndReason::Unresponsive));
+        self.pending_promotion_move = None;
+        *connection = None;
+    }
+
+    pub fn flagged(&self) -> Option<PieceColor> {
+        self.clock.as_ref().and_then(|clock| clock.flagged())
+    }
+}
+
+/// The position a `Start` packet sets up, a bad fen from the peer is a bad packet
+fn start_position(start: &chess_networking::Start) -> Result<Position, NetworkError> {
+    Position::from_fen(
+        start
+            .fen
+            .as_deref()
+            .unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"),
+    )
+    .map_err(|_| NetworkError::BadPacket)
+}
+
+/// The side of a self-play game that isn't currently shown. Both sides talk over a loopback
+/// and trade places with the shown `ClientGameState` whenever the turn passes.
+#[derive(Resource)]
+pub struct SelfPlay {
+    pub game_state: ClientGameState,
+    pub connection: Box<dyn Transport>,
+}
+
+/// A local two-player game. Nothing goes over the network, the players take turns at the same
+/// window and whoever is to move owns the board.
+#[derive(Resource)]
+pub struct HotSeat {
+    pub start: chess_networking::Start,
+    /// Turn the camera to the side to move after each move
+    pub rotate_camera: bool,
+    /// Where the camera is turned to around the board, 0 is white's side
+    pub camera_angle: f32,
 }
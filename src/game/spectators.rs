@@ -0,0 +1,141 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener},
+};
+
+use bevy::prelude::Resource;
+use vhultman_chess::ChessMove;
+
+use super::networking::{spectator_port, Connection, Message, NetworkError, Transport};
+use super::utils::move_to_packet;
+
+/// Read-only watchers of a hosted game. They connect to the port after the game's one, send a
+/// `Start` like a client would and get the game's `Start` followed by every accepted move.
+/// Nothing they send after that is looked at.
+#[derive(Resource)]
+pub(crate) struct Spectators {
+    listener: TcpListener,
+    /// Connected but their `Start` hasn't arrived yet
+    joining: Vec<Connection>,
+    watching: Vec<Spectator>,
+    /// The game's takebacks when we last sent anything, see `ClientGameState::takebacks`
+    takebacks: u32,
+}
+
+struct Spectator {
+    connection: Connection,
+    name: String,
+    /// Moves of the current game they have, `None` until they got its `Start`
+    moves_sent: Option<usize>,
+}
+
+impl Spectators {
+    pub fn new(game_address: SocketAddr) -> std::io::Result<Self> {
+        let mut address = game_address;
+        address.set_port(spectator_port(address.port()));
+
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Spectators {
+            listener,
+            joining: Vec::new(),
+            watching: Vec::new(),
+            takebacks: 0,
+        })
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.listener
+            .local_addr()
+            .ok()
+            .map(|address| address.port())
+    }
+
+    /// Everyone gets the next game from its `Start` again, like after a rematch
+    pub fn restart(&mut self) {
+        for spectator in &mut self.watching {
+            spectator.moves_sent = None;
+        }
+    }
+
+    /// Takes in new spectators and sends everyone what they haven't seen yet of the game
+    /// set up by `start`. Moves can only be added, so after a takeback they start over.
+    pub fn update(&mut self, start: &chess_networking::Start, moves: &[ChessMove], takebacks: u32) {
+        if takebacks != self.takebacks {
+            self.takebacks = takebacks;
+            self.restart();
+        }
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => match Connection::from_stream(stream) {
+                    Ok(connection) => {
+                        println!("Spectator connected from {}", address);
+                        self.joining.push(connection);
+                    }
+                    Err(e) => println!("Failed to set up spectator connection: {}", e),
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Failed to accept spectator: {}", e);
+                    break;
+                }
+            }
+        }
+
+        for mut connection in std::mem::take(&mut self.joining) {
+            match connection.read() {
+                Ok(Some(Message::Start(packet, _))) => {
+                    let name = packet.name.unwrap_or("anonymous".to_string());
+                    println!("{} is spectating", name);
+                    self.watching.push(Spectator {
+                        connection,
+                        name,
+                        moves_sent: None,
+                    });
+                }
+                Ok(None) => self.joining.push(connection),
+                Ok(Some(_)) => println!("Dropped a spectator that didn't send a start packet"),
+                Err(e) => println!("Dropped a joining spectator: {}", e),
+            }
+        }
+
+        self.watching
+            .retain_mut(|spectator| match spectator.catch_up(start, moves) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Spectator {} left: {}", spectator.name, e);
+                    false
+                }
+            });
+    }
+}
+
+impl Spectator {
+    fn catch_up(
+        &mut self,
+        start: &chess_networking::Start,
+        moves: &[ChessMove],
+    ) -> Result<(), NetworkError> {
+        // reading only notices them leaving
+        while self.connection.read()?.is_some() {}
+
+        let sent = match self.moves_sent {
+            Some(sent) => sent.min(moves.len()),
+            None => {
+                // spectators only follow the game, there's nothing to negotiate with them
+                self.connection
+                    .write(Message::Start(start.clone(), Vec::new()))?;
+                0
+            }
+        };
+
+        for m in &moves[sent..] {
+            self.connection.write(Message::Move(move_to_packet(*m)))?;
+        }
+        self.moves_sent = Some(moves.len());
+
+        Ok(())
+    }
+}
@@ -2,19 +2,26 @@ use bevy::prelude::*;
 use bevy_mod_outline::{OutlineBundle, OutlineMode, OutlineVolume};
 use bevy_mod_picking::PickableBundle;
 use chess_networking::PromotionPiece;
-use vhultman_chess::{Color as PieceColor, GameState, Piece, PieceType};
+use vhultman_chess::{Color as PieceColor, Piece, PieceType};
 
 use std::f32::consts::PI;
+use std::time::Duration;
 
 use bevy::prelude::Color;
 
+use crate::game::engine::EngineOpponent;
+use crate::game::extension::Extension;
+use crate::game::networking::{Message, NetworkError, Transport};
 use crate::game::{
-    board_id_to_world_pos, world_pos_to_board_id, ChessPiece, ChessPiecePart, ClientGameState,
-    NetworkState, OnGameScreen, PieceModelData,
+    board_id_to_world_pos, world_pos_to_board_id, ChatLine, ChessPiece, ChessPiecePart,
+    ClientGameState, EndReason, GameResult, NetworkState, OnGameScreen, PeerCapabilities,
+    PieceModelData, SelfPlay,
 };
-use crate::general::resources::NetworkHandler;
+use crate::general::resources::{HostSettings, NetworkHandler, NetworkRole};
 use crate::SoundEffects;
 
+use super::reconnect;
+
 pub(crate) fn spawn_piece(
     commands: &mut Commands,
     piece_model_data: &PieceModelData,
@@ -216,102 +223,679 @@ pub(crate) fn update_board(
 }
 
 pub(crate) fn wait_for_move(
+    mut commands: Commands,
     mut game_state: ResMut<ClientGameState>,
     mut network_handler: ResMut<NetworkHandler>,
+    host_settings: Res<HostSettings>,
+    self_play: Option<Res<SelfPlay>>,
+    engine: Option<Res<EngineOpponent>>,
 ) {
-    if game_state.network_state == NetworkState::Normal {
+    let Some(connection) = network_handler.connection.as_mut() else {
         return;
-    }
+    };
 
-    //let role = network_handler.role;
-    if let Some(connection) = network_handler.connection.as_mut() {
-        // wait for start packet from server
-        let buf: Vec<u8> = connection.read();
-        if buf.is_empty() {
-            return;
+    // read even when it's our turn so a closed connection is noticed right away
+    if let Err(e) = receive_packets(&mut game_state, connection.as_mut()) {
+        let disconnected = matches!(e, NetworkError::Disconnected);
+        network_handler.fail(e);
+
+        // the host keeps the game around for the opponent to come back to, a lobby game has
+        // nobody listening for them
+        if disconnected
+            && network_handler.role == NetworkRole::Server
+            && network_handler.lobby_address.is_none()
+            && self_play.is_none()
+            && engine.is_none()
+            && !game_state.is_game_over()
+        {
+            reconnect::start_reconnect(
+                &mut commands,
+                &network_handler,
+                &host_settings,
+                &game_state,
+            );
         }
+    }
+}
 
-        if game_state.network_state == NetworkState::AwaitingAck {
-            let packet = chess_networking::Ack::try_from(&buf as &[u8]).expect("Bad packet");
+/// Handles everything the opponent sent since the last call
+pub(crate) fn receive_packets(
+    game_state: &mut ClientGameState,
+    connection: &mut dyn Transport,
+) -> Result<(), NetworkError> {
+    while let Some(message) = connection.read()? {
+        game_state.opponent_silence = Duration::ZERO;
+
+        match message {
+            // chat goes on whatever happens on the board
+            Message::Extension(Extension::Chat(text)) => {
+                game_state.chat.push(ChatLine { own: false, text });
+            }
+            Message::Extension(Extension::Capabilities { version, features }) => {
+                game_state.announced_capabilities = Some(PeerCapabilities {
+                    version: Some(version),
+                    features,
+                });
+            }
+            Message::Extension(Extension::Takeback { plies, moves }) => {
+                if let Some(answer) = game_state.receive_takeback(plies, moves) {
+                    connection.write(Message::Extension(answer))?;
+                }
+            }
+            Message::Extension(Extension::TakebackAnswer(accepted)) => {
+                game_state.receive_takeback_answer(accepted)?;
+            }
+            // from a newer client, it only relies on what our capabilities list
+            Message::Extension(Extension::Unknown(name)) => {
+                println!("Ignored the unknown extension {}", name);
+            }
+            // the next game, see rematch
+            Message::Start(packet, _) if game_state.is_game_over() => {
+                game_state.rematch_start = Some(packet);
+            }
+            // the game is over on time, whatever arrives now is too late
+            _ if game_state.flagged().is_some() => {}
+            // same once we've given up on them
+            _ if game_state
+                .result
+                .is_some_and(|result| result.reason == EndReason::Unresponsive) => {}
+            Message::Move(packet) if packet.forfeit => {
+                let own_color = game_state.own_color;
+                game_state
+                    .result
+                    .get_or_insert(GameResult::win(own_color, EndReason::Forfeit));
+
+                // the forfeit takes the place of their move so it gets acked like one
+                if game_state.network_state == NetworkState::AwaitingMove {
+                    game_state.network_state = NetworkState::Normal;
+                    connection.write(Message::Ack(chess_networking::Ack {
+                        ok: true,
+                        end_state: None,
+                    }))?;
+                }
+            }
+            // ack for our own forfeit, nothing left to do
+            Message::Ack(_)
+                if game_state.result.is_some_and(|result| {
+                    result.reason == EndReason::Forfeit
+                        && result.loser() == Some(game_state.own_color)
+                }) => {}
+            Message::Ack(packet) if game_state.network_state == NetworkState::AwaitingAck => {
+                game_state.outgoing_draw_offer = false;
+                if packet.ok {
+                    game_state.network_state = NetworkState::AwaitingMove;
+                    println!("received ack packet, its ok! time to make a move for us!");
+
+                    // our move already ended the game if it was checkmate or a draw on the board,
+                    // anything else the opponent reports was agreed on or is their view of it
+                    if game_state.result.is_none() {
+                        let own_color = game_state.own_color;
+                        game_state.result = match packet.end_state {
+                            Some(chess_networking::GameState::CheckMate) => {
+                                Some(GameResult::win(own_color, EndReason::Checkmate))
+                            }
+                            Some(chess_networking::GameState::Draw) => {
+                                Some(GameResult::draw(EndReason::Agreement))
+                            }
+                            None => None,
+                        };
+                    }
+                } else {
+                    game_state.result = Some(GameResult::win(
+                        game_state.server_color,
+                        EndReason::IllegalMove,
+                    ));
+                }
+            }
+            Message::Move(packet) if game_state.network_state == NetworkState::AwaitingMove => {
+                let move_accepted = apply_opponent_move(game_state, &packet);
+
+                // the server wins on an illegal move, also when it's the server rejecting it
+                if !move_accepted {
+                    game_state.result = Some(GameResult::win(
+                        game_state.server_color,
+                        EndReason::IllegalMove,
+                    ));
+                }
 
-            game_state.next_ack_state = packet.end_state;
+                // the ack is sent once the player has accepted or declined
+                if move_accepted && packet.offer_draw && !game_state.is_game_over() {
+                    game_state.incoming_draw_offer = true;
+                    continue;
+                }
 
-            if packet.ok {
-                game_state.network_state = NetworkState::AwaitingMove;
-                println!("received ack packet, its ok! time to make a move for us!");
-            } else {
-                // an illegal move is supposed to make the server win (no matter who doesn't accept
-                // the move), but endstate in the networking specs doesn't support setting who
-                // checkmated who so im just setting checmkate for now no matter what in next ack packet
-                // thus logic below is commented out
-
-                //if role == NetworkRole::Server {
-                //    // we just won, time to send out end state
-                //} else {
-                //    // we just lost, time to resign
-                //}
-
-                game_state.next_ack_state = Some(chess_networking::GameState::CheckMate);
-            }
+                if move_accepted {
+                    game_state.network_state = NetworkState::Normal;
+                }
 
-            return;
+                connection.write(Message::Ack(chess_networking::Ack {
+                    ok: move_accepted,
+                    end_state: game_state.result.and_then(|result| result.end_state()),
+                }))?;
+            }
+            _ => return Err(NetworkError::UnexpectedPacket),
         }
+    }
+
+    Ok(())
+}
+
+/// Plays a move received from the opponent if it's legal, returns whether it was
+pub(crate) fn apply_opponent_move(
+    game_state: &mut ClientGameState,
+    packet: &chess_networking::Move,
+) -> bool {
+    let from_id = square_coords_to_id(packet.from);
+    let to_id = square_coords_to_id(packet.to);
+
+    let possible_moves: Vec<u32> = game_state
+        .board_state
+        .moves_for_square(from_id)
+        .iter()
+        .map(|m| m.to())
+        .collect();
 
-        println!("{:?}", buf);
-        let packet = chess_networking::Move::try_from(&buf as &[u8]).expect("Bad packet");
+    if !possible_moves.contains(&to_id) {
+        return false;
+    }
 
-        let from_id = square_coords_to_id(packet.from);
-        let to_id = square_coords_to_id(packet.to);
+    let Some(mut m) = game_state.board_state.get_move(from_id, to_id) else {
+        return false;
+    };
+
+    if let Some(promotion_piece) = &packet.promotion {
+        m.set_promotion_piece(match promotion_piece {
+            PromotionPiece::Rook => PieceType::Rook,
+            PromotionPiece::Knight => PieceType::Knight,
+            PromotionPiece::Bishop => PieceType::Bishop,
+            PromotionPiece::Queen => PieceType::Queen,
+        });
+    }
+
+    game_state.board_state.make_move(m);
+    game_state.history.push(m);
+    game_state.last_move = Some(m);
+    game_state.board_dirty = true;
+
+    game_state.update_result_from_board();
+
+    true
+}
 
-        let possible_moves: Vec<u32> = game_state
+fn square_coords_to_id(coords: (u8, u8)) -> u32 {
+    ((7 - coords.1) * 8 + coords.0).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::networking::{client_start, Loopback};
+    use crate::game::{move_name, position_to_fen};
+
+    type Side = (ClientGameState, Loopback);
+
+    /// Server plays white from the standard position, without a clock
+    fn new_game() -> (Side, Side) {
+        let start = chess_networking::Start {
+            is_white: true,
+            name: None,
+            fen: None,
+            time: None,
+            inc: None,
+        };
+        let (server_connection, client_connection) = Loopback::pair();
+
+        (
+            (
+                ClientGameState::from_start(&start, NetworkRole::Server).unwrap(),
+                server_connection,
+            ),
+            (
+                ClientGameState::from_start(&start, NetworkRole::Client).unwrap(),
+                client_connection,
+            ),
+        )
+    }
+
+    fn history(side: &Side) -> Vec<String> {
+        side.0.history.iter().map(|m| move_name(*m)).collect()
+    }
+
+    fn square(name: &str) -> u32 {
+        let name = name.as_bytes();
+        let x = (name[0] - b'a') as u32;
+        let y = (name[1] - b'1') as u32;
+        (7 - y) * 8 + x
+    }
+
+    /// Plays a move for the side to move the same way the input systems do, then lets the
+    /// opponent receive it and the mover receive the ack
+    fn play(white: &mut Side, black: &mut Side, from: &str, to: &str) {
+        let white_to_move = white.0.board_state.current_side() == PieceColor::White;
+        let (mover, other) = if white_to_move {
+            (white, black)
+        } else {
+            (black, white)
+        };
+
+        assert_eq!(mover.0.network_state, NetworkState::Normal);
+        assert_eq!(other.0.network_state, NetworkState::AwaitingMove);
+
+        let m = mover
+            .0
             .board_state
-            .moves_for_square(from_id)
-            .iter()
-            .map(|m| m.to())
-            .collect();
-
-        let mut move_accepted = false;
-        if possible_moves.contains(&to_id) {
-            let mut possible_move = game_state.board_state.get_move(from_id, to_id);
-
-            if let Some(m) = possible_move.as_mut() {
-                if let Some(promotion_piece) = packet.promotion {
-                    m.set_promotion_piece(match promotion_piece {
-                        PromotionPiece::Rook => PieceType::Rook,
-                        PromotionPiece::Knight => PieceType::Knight,
-                        PromotionPiece::Bishop => PieceType::Bishop,
-                        PromotionPiece::Queen => PieceType::Queen,
-                    });
-                }
+            .get_move(square(from), square(to))
+            .expect("test move should be legal");
+        let packet = mover.0.play_own_move(m);
+        mover.1.write(Message::Move(packet)).unwrap();
 
-                game_state.board_state.make_move(*m);
-                game_state.last_move = Some(*m);
-                move_accepted = true;
-                game_state.board_dirty = true;
+        receive_packets(&mut other.0, &mut other.1).unwrap();
+        receive_packets(&mut mover.0, &mut mover.1).unwrap();
+    }
 
-                if game_state.next_ack_state.is_none() {
-                    if let GameState::Checkmate = game_state.board_state.check_game_state() {
-                        game_state.next_ack_state = Some(chess_networking::GameState::CheckMate)
-                    }
-                };
-            }
+    #[test]
+    fn two_clients_play_to_checkmate() {
+        let (mut white, mut black) = new_game();
+
+        for (from, to) in [
+            ("e2", "e4"),
+            ("e7", "e5"),
+            ("f1", "c4"),
+            ("b8", "c6"),
+            ("d1", "h5"),
+            ("g8", "f6"),
+        ] {
+            play(&mut white, &mut black, from, to);
+
+            assert_eq!(
+                position_to_fen(&white.0.board_state, white.0.last_move),
+                position_to_fen(&black.0.board_state, black.0.last_move)
+            );
+            assert!(!white.0.is_game_over());
+            assert!(!black.0.is_game_over());
         }
 
-        if move_accepted {
-            game_state.network_state = NetworkState::Normal;
+        play(&mut white, &mut black, "h5", "f7");
+
+        // both sides see the mate on their own board
+        let mate = Some(GameResult::win(PieceColor::White, EndReason::Checkmate));
+        assert_eq!(white.0.result, mate);
+        assert_eq!(black.0.result, mate);
+    }
+
+    #[test]
+    fn illegal_move_is_rejected() {
+        let (mut white, mut black) = new_game();
+
+        white
+            .1
+            .write(Message::Move(chess_networking::Move {
+                from: (4, 1),
+                to: (4, 4),
+                promotion: None,
+                forfeit: false,
+                offer_draw: false,
+            }))
+            .unwrap();
+        white.0.network_state = NetworkState::AwaitingAck;
+
+        receive_packets(&mut black.0, &mut black.1).unwrap();
+        assert_eq!(black.0.network_state, NetworkState::AwaitingMove);
+        assert!(black.0.last_move.is_none());
+
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+
+        // white is the server and wins even though it made the illegal move
+        let illegal = Some(GameResult::win(PieceColor::White, EndReason::IllegalMove));
+        assert_eq!(white.0.result, illegal);
+        assert_eq!(black.0.result, illegal);
+    }
+
+    #[test]
+    fn missing_ack_lets_the_win_be_claimed() {
+        use crate::game::networking::ACK_TIMEOUT;
+
+        let (mut white, mut black) = new_game();
+
+        // the opponent may think as long as they want, keepalive notices if they're gone
+        black.0.opponent_silence = Duration::from_secs(3600);
+        assert!(!black.0.opponent_unresponsive());
+        assert_eq!(white.0.response_timeout(), None);
+
+        let m = white
+            .0
+            .board_state
+            .get_move(square("e2"), square("e4"))
+            .unwrap();
+        let packet = white.0.play_own_move(m);
+        white.1.write(Message::Move(packet)).unwrap();
+        assert!(!white.0.opponent_unresponsive());
+
+        // black never acks
+        white.0.opponent_silence = ACK_TIMEOUT;
+        assert!(white.0.opponent_unresponsive());
+        assert!(white.0.result.is_none());
+
+        // hearing from them again starts the timeout over
+        receive_packets(&mut black.0, &mut black.1).unwrap();
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+        assert_eq!(white.0.opponent_silence, Duration::ZERO);
+        assert!(!white.0.opponent_unresponsive());
+    }
+
+    #[test]
+    fn claiming_the_win_hangs_up() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+
+        let mut connection: Option<Box<dyn Transport>> = Some(Box::new(white.1));
+        white.0.claim_win(&mut connection);
+        assert!(connection.is_none());
+        assert_eq!(
+            white.0.result,
+            Some(GameResult::win(PieceColor::White, EndReason::Unresponsive))
+        );
+
+        // black's next move finds nobody there
+        let m = black
+            .0
+            .board_state
+            .get_move(square("e7"), square("e5"))
+            .unwrap();
+        let packet = black.0.play_own_move(m);
+        assert!(matches!(
+            black.1.write(Message::Move(packet)),
+            Err(NetworkError::Disconnected)
+        ));
+        assert!(matches!(
+            receive_packets(&mut black.0, &mut black.1),
+            Err(NetworkError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn draw_offer_waits_for_an_answer() {
+        use crate::game::networking::ACK_TIMEOUT;
+
+        let (mut white, mut black) = new_game();
+
+        white.0.offer_draw = true;
+        let m = white
+            .0
+            .board_state
+            .get_move(square("e2"), square("e4"))
+            .unwrap();
+        let packet = white.0.play_own_move(m);
+        white.1.write(Message::Move(packet)).unwrap();
+        receive_packets(&mut black.0, &mut black.1).unwrap();
+        assert!(black.0.incoming_draw_offer);
+
+        // black holds back the ack while thinking it over
+        white.0.opponent_silence = ACK_TIMEOUT * 10;
+        assert!(white.0.outgoing_draw_offer);
+        assert!(!white.0.opponent_unresponsive());
+
+        // declined, the game goes on
+        black.0.incoming_draw_offer = false;
+        black.0.network_state = NetworkState::Normal;
+        black
+            .1
+            .write(Message::Ack(chess_networking::Ack {
+                ok: true,
+                end_state: None,
+            }))
+            .unwrap();
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+        assert!(!white.0.outgoing_draw_offer);
+        assert_eq!(white.0.network_state, NetworkState::AwaitingMove);
+        assert!(white.0.result.is_none());
+    }
+
+    #[test]
+    fn reconnect_resumes_from_the_host_position() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+
+        // black's reply never reaches the host before the connection breaks
+        let m = black
+            .0
+            .board_state
+            .get_move(square("e7"), square("e5"))
+            .unwrap();
+        black.0.play_own_move(m);
+
+        let start = white.0.resume_packet("Servermannen");
+        let (host_connection, client_connection) = Loopback::pair();
+        white.1 = host_connection;
+        black.1 = client_connection;
+        white.0.resume(&start, NetworkRole::Server).unwrap();
+        black.0.resume(&start, NetworkRole::Client).unwrap();
+
+        assert_eq!(
+            position_to_fen(&white.0.board_state, None),
+            position_to_fen(&black.0.board_state, None)
+        );
+        assert!(black.0.board_state.piece_on(square("e5")).is_none());
+        assert_eq!(black.0.own_color, PieceColor::Black);
+        // the lost move is gone from the history too
+        assert_eq!(history(&white), ["e2e4"]);
+        assert_eq!(history(&black), ["e2e4"]);
+
+        // and the lost move can be played again
+        play(&mut white, &mut black, "e7", "e5");
+        play(&mut white, &mut black, "g1", "f3");
+    }
+
+    #[test]
+    fn resuming_from_a_bad_position_is_an_error() {
+        let (_, mut black) = new_game();
+        let start = chess_networking::Start {
+            is_white: true,
+            name: None,
+            fen: Some("not a fen".to_string()),
+            time: None,
+            inc: None,
+        };
+
+        assert!(matches!(
+            black.0.resume(&start, NetworkRole::Client),
+            Err(NetworkError::BadPacket)
+        ));
+        // the game is left as it was
+        assert!(black.0.board_state.piece_on(square("e2")).is_some());
+    }
+
+    #[test]
+    fn reconnect_catches_up_on_the_host_move() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+        play(&mut white, &mut black, "e7", "e5");
+
+        // the host's move never reaches the client before the connection breaks
+        let m = white
+            .0
+            .board_state
+            .get_move(square("g1"), square("f3"))
+            .unwrap();
+        white.0.play_own_move(m);
+
+        let start = white.0.resume_packet("Servermannen");
+        white.0.resume(&start, NetworkRole::Server).unwrap();
+        black.0.resume(&start, NetworkRole::Client).unwrap();
+
+        assert_eq!(history(&black), ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(black.0.network_state, NetworkState::Normal);
+    }
+
+    #[test]
+    fn start_after_the_game_is_kept_for_a_rematch() {
+        let (mut white, mut black) = new_game();
+
+        // a start in the middle of a game is still wrong
+        black
+            .1
+            .write(Message::start(client_start("Klientmannen")))
+            .unwrap();
+        assert!(matches!(
+            receive_packets(&mut white.0, &mut white.1),
+            Err(NetworkError::UnexpectedPacket)
+        ));
+
+        white.0.result = Some(GameResult::win(PieceColor::Black, EndReason::Forfeit));
+        black
+            .1
+            .write(Message::start(client_start("Klientmannen")))
+            .unwrap();
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+
+        let start = white
+            .0
+            .rematch_start
+            .as_ref()
+            .expect("start should be kept");
+        assert_eq!(start.name.as_deref(), Some("Klientmannen"));
+    }
+
+    #[test]
+    fn capabilities_arrive_during_the_game() {
+        let (mut white, mut black) = new_game();
+
+        black
+            .1
+            .write(Message::Extension(Extension::Capabilities {
+                version: 1,
+                features: vec!["chat".to_string()],
+            }))
+            .unwrap();
+        play(&mut white, &mut black, "e2", "e4");
+
+        let capabilities = white
+            .0
+            .announced_capabilities
+            .take()
+            .expect("capabilities should be kept");
+        assert_eq!(capabilities.version, Some(1));
+        assert!(capabilities.supports("chat") && !capabilities.supports("takeback"));
+    }
+
+    #[test]
+    fn accepted_takeback_brings_captured_pieces_back() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+        play(&mut white, &mut black, "d7", "d5");
+        play(&mut white, &mut black, "e4", "d5");
+
+        // black's move and the capture that answered it
+        let request = black
+            .0
+            .request_takeback()
+            .expect("black has a move to take back");
+        assert_eq!(request, Extension::Takeback { plies: 2, moves: 3 });
+        black.1.write(Message::Extension(request)).unwrap();
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+
+        let answer = white.0.answer_takeback(true).unwrap();
+        white.1.write(Message::Extension(answer)).unwrap();
+        receive_packets(&mut black.0, &mut black.1).unwrap();
+
+        for side in [&white, &black] {
+            assert_eq!(side.0.history.len(), 1);
+            assert!(side.0.takeback.is_none());
+            assert!(side.0.board_dirty);
+            let pawn = side.0.board_state.piece_on(square("d7")).unwrap();
+            assert!(pawn.t == PieceType::Pawn && pawn.color == PieceColor::Black);
+            assert!(side.0.board_state.piece_on(square("d5")).is_none());
         }
+        assert_eq!(
+            position_to_fen(&white.0.board_state, None),
+            position_to_fen(&black.0.board_state, None)
+        );
 
-        connection.write(
-            (chess_networking::Ack {
-                ok: move_accepted,
-                end_state: game_state.next_ack_state.clone(),
-            })
-            .try_into()
-            .unwrap(),
+        // and the game goes on from there
+        play(&mut white, &mut black, "e7", "e5");
+    }
+
+    #[test]
+    fn takeback_after_a_reconnect_replays_the_resumed_game() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+        play(&mut white, &mut black, "e7", "e5");
+
+        // the host's move is lost, the client only learns about it from the resume
+        let m = white
+            .0
+            .board_state
+            .get_move(square("g1"), square("f3"))
+            .unwrap();
+        white.0.play_own_move(m);
+
+        let start = white.0.resume_packet("Servermannen");
+        let (host_connection, client_connection) = Loopback::pair();
+        white.1 = host_connection;
+        black.1 = client_connection;
+        white.0.resume(&start, NetworkRole::Server).unwrap();
+        black.0.resume(&start, NetworkRole::Client).unwrap();
+
+        // black's move and the one that answered it
+        let request = black.0.request_takeback().unwrap();
+        assert_eq!(request, Extension::Takeback { plies: 2, moves: 3 });
+        black.1.write(Message::Extension(request)).unwrap();
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+
+        let answer = white.0.answer_takeback(true).unwrap();
+        assert_eq!(answer, Extension::TakebackAnswer(true));
+        white.1.write(Message::Extension(answer)).unwrap();
+        receive_packets(&mut black.0, &mut black.1).unwrap();
+
+        assert_eq!(history(&white), ["e2e4"]);
+        assert_eq!(history(&black), ["e2e4"]);
+        assert_eq!(
+            position_to_fen(&white.0.board_state, None),
+            position_to_fen(&black.0.board_state, None)
         );
+
+        play(&mut white, &mut black, "c7", "c5");
     }
-}
 
-fn square_coords_to_id(coords: (u8, u8)) -> u32 {
-    ((7 - coords.1) * 8 + coords.0).into()
+    #[test]
+    fn takeback_crossing_a_move_is_declined() {
+        let (mut white, mut black) = new_game();
+        play(&mut white, &mut black, "e2", "e4");
+
+        let request = white.0.request_takeback().unwrap();
+        white.1.write(Message::Extension(request)).unwrap();
+
+        // black moves before the request arrives
+        play(&mut white, &mut black, "e7", "e5");
+        receive_packets(&mut white.0, &mut white.1).unwrap();
+
+        for side in [&white, &black] {
+            assert_eq!(side.0.history.len(), 2);
+            assert!(side.0.takeback.is_none());
+        }
+    }
+
+    #[test]
+    fn hot_seat_hands_the_board_to_the_side_to_move() {
+        let start = chess_networking::Start {
+            is_white: true,
+            name: None,
+            fen: Some("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string()),
+            time: None,
+            inc: None,
+        };
+        let mut game_state = ClientGameState::hot_seat(&start).unwrap();
+        assert_eq!(game_state.own_color, PieceColor::Black);
+        assert_eq!(game_state.network_state, NetworkState::Normal);
+
+        let m = game_state
+            .board_state
+            .get_move(square("e7"), square("e5"))
+            .unwrap();
+        game_state.play_own_move(m);
+        game_state.pass_turn();
+
+        assert_eq!(game_state.own_color, PieceColor::White);
+        assert_eq!(game_state.network_state, NetworkState::Normal);
+    }
 }
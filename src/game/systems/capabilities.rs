@@ -0,0 +1,46 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::{
+        extension::{self, Extension},
+        networking::Message,
+        ClientGameState, PeerCapabilities,
+    },
+    general::resources::NetworkHandler,
+};
+
+/// Starts off `PeerCapabilities` with what the opponent listed in its `Start` and sends ours if
+/// it listed anything. Clients that didn't are never sent them, so neither side has to know
+/// about the exchange.
+pub(crate) fn exchange_capabilities(
+    commands: &mut Commands,
+    network_handler: &mut NetworkHandler,
+    peer_extensions: Vec<String>,
+) {
+    let capabilities = PeerCapabilities::from_start(peer_extensions);
+    if capabilities.any() {
+        network_handler.send(Message::Extension(Extension::Capabilities {
+            version: extension::PROTOCOL_VERSION,
+            features: extension::supported(),
+        }));
+    }
+
+    commands.insert_resource(capabilities);
+}
+
+/// Takes over the capabilities once the opponent's arrive
+pub(crate) fn receive_capabilities(
+    mut game_state: ResMut<ClientGameState>,
+    mut peer_capabilities: ResMut<PeerCapabilities>,
+) {
+    let Some(capabilities) = game_state.announced_capabilities.take() else {
+        return;
+    };
+
+    println!(
+        "Opponent speaks version {} and supports [{}]",
+        capabilities.version.unwrap_or_default(),
+        capabilities.features.join(", ")
+    );
+    *peer_capabilities = capabilities;
+}
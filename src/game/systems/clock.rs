@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+
+use crate::game::{other_color, ClientGameState, EndReason, GameResult};
+use crate::general::resources::NetworkHandler;
+
+pub(crate) fn tick_clock(
+    time: Res<Time>,
+    mut game_state: ResMut<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+) {
+    if game_state.is_game_over() || network_handler.error.is_some() {
+        return;
+    }
+
+    let side = game_state.board_state.current_side();
+    if let Some(clock) = game_state.clock.as_mut() {
+        clock.tick(side, time.delta());
+    }
+
+    if let Some(color) = game_state.flagged() {
+        game_state.result = Some(GameResult::win(other_color(color), EndReason::Timeout));
+    }
+}
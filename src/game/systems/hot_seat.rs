@@ -0,0 +1,52 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use vhultman_chess::Color as PieceColor;
+
+use crate::game::{ClientGameState, HotSeat, NetworkState};
+
+/// How fast the camera turns around the board, in radians per second
+const CAMERA_TURN_SPEED: f32 = PI;
+
+/// Where the camera looks at the board from, on white's side
+const CAMERA_POSITION: Vec3 = Vec3::new(0.0, 10.0, 8.0);
+
+/// Gives the board to the other player once a move was played, there's no opponent to ack it
+pub(crate) fn pass_turn(mut game_state: ResMut<ClientGameState>) {
+    if game_state.network_state == NetworkState::AwaitingAck {
+        game_state.pass_turn();
+    }
+}
+
+/// Turns the camera around to the side to move, if the players asked for that
+pub(crate) fn turn_camera(
+    time: Res<Time>,
+    mut hot_seat: ResMut<HotSeat>,
+    game_state: Res<ClientGameState>,
+    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+) {
+    if !hot_seat.rotate_camera || game_state.is_game_over() {
+        return;
+    }
+
+    let target = match game_state.board_state.current_side() {
+        PieceColor::White => 0.0,
+        PieceColor::Black => PI,
+    };
+    if hot_seat.camera_angle == target {
+        return;
+    }
+
+    let step = CAMERA_TURN_SPEED * time.delta_seconds();
+    let remaining = target - hot_seat.camera_angle;
+    hot_seat.camera_angle = if remaining.abs() <= step {
+        target
+    } else {
+        hot_seat.camera_angle + step * remaining.signum()
+    };
+
+    let position = Quat::from_rotation_y(hot_seat.camera_angle) * CAMERA_POSITION;
+    for mut transform in camera_query.iter_mut() {
+        *transform = Transform::from_translation(position).looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
@@ -5,6 +5,7 @@ use bevy_mod_picking::*;
 use events::{Click, Pointer};
 use vhultman_chess::PieceType;
 
+use crate::game::networking::Message;
 use crate::game::{
     world_pos_to_board_id, ChessPiece, ChessPiecePart, ChessSquare, ClientGameState, NetworkState,
     SquareResourceData,
@@ -24,7 +25,11 @@ pub fn handle_picking(
     sound_effects: Res<SoundEffects>,
     mut network_handler: ResMut<NetworkHandler>,
 ) {
-    if game_state.network_state != NetworkState::Normal {
+    if game_state.network_state != NetworkState::Normal
+        || network_handler.error.is_some()
+        || game_state.is_game_over()
+        || game_state.takeback.is_some()
+    {
         return;
     }
 
@@ -111,26 +116,8 @@ pub fn handle_picking(
                                 m.set_promotion_piece(PieceType::Queen);
                                 game_state.pending_promotion_move = Some(m);
                             } else {
-                                game_state.pending_promotion_move = None;
-                                game_state.board_state.make_move(m);
-                                game_state.network_state = NetworkState::AwaitingAck;
-
-                                let move_buf: Vec<u8> = chess_networking::Move {
-                                    from: (m.from() as u8 % 8, 7 - (m.from() as u8 / 8)),
-                                    to: (m.to() as u8 % 8, 7 - (m.to() as u8 / 8)),
-                                    promotion: None,
-                                    forfeit: false,
-                                    offer_draw: false,
-                                }
-                                .try_into()
-                                .unwrap();
-
-                                if let Some(connection) = network_handler.connection.as_mut() {
-                                    connection.write(move_buf);
-                                }
-
-                                game_state.last_move = Some(m);
-                                game_state.board_dirty = true;
+                                let move_packet = game_state.play_own_move(m);
+                                network_handler.send(Message::Move(move_packet));
                             }
                         }
                     } else {
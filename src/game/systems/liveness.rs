@@ -0,0 +1,19 @@
+use bevy::prelude::*;
+
+use crate::game::ClientGameState;
+use crate::general::resources::NetworkHandler;
+
+/// Times how long the opponent has kept us waiting. A dead connection is noticed by tcp
+/// keepalive, this catches an opponent that is connected but doesn't answer. Only the player
+/// ends the game over it, see `not_responding_action`.
+pub(crate) fn watch_opponent(
+    time: Res<Time>,
+    mut game_state: ResMut<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+) {
+    if network_handler.error.is_some() || game_state.response_timeout().is_none() {
+        return;
+    }
+
+    game_state.opponent_silence += time.delta();
+}
@@ -1,4 +1,12 @@
 pub mod board;
+pub mod capabilities;
+pub mod clock;
+pub mod hot_seat;
 pub mod input;
+pub mod liveness;
+pub mod reconnect;
+pub mod rematch;
 pub mod resource_setup;
+pub mod self_play;
 pub mod setup;
+pub mod spectate;
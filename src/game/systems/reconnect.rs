@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+
+use crate::game::capture;
+use crate::game::networking::{self, ConnectThread};
+use crate::game::ClientGameState;
+use crate::general::resources::{HostSettings, NetworkHandler, NetworkRole};
+
+use super::capabilities;
+
+/// Getting an interrupted game's connection back in the background. The host waits for the
+/// opponent to return, the client connects to the host again. Removing it stops trying.
+#[derive(Resource)]
+pub(crate) struct Reconnection {
+    connect: ConnectThread,
+}
+
+/// Starts getting the connection back, the game goes on from the host's position once it's there
+pub(crate) fn start_reconnect(
+    commands: &mut Commands,
+    network_handler: &NetworkHandler,
+    host_settings: &HostSettings,
+    game_state: &ClientGameState,
+) {
+    let role = network_handler.role;
+    let name = network_handler.display_name();
+    let capture = network_handler
+        .record_traffic
+        .then(|| capture::new_capture_path(role));
+    let password = network_handler.password.clone();
+
+    let connect = match role {
+        NetworkRole::Server => {
+            let address = host_settings.bind_address.to_string();
+            let start = game_state.resume_packet(&name);
+            let opponent_name = network_handler.opponent_name.clone();
+            let resume_token = network_handler.resume_token.clone();
+
+            println!("Waiting for {} to reconnect", address);
+            ConnectThread::spawn(move |cancel| {
+                networking::await_reconnect(
+                    &address,
+                    start,
+                    opponent_name,
+                    resume_token,
+                    password.as_deref(),
+                    capture.as_deref(),
+                    cancel,
+                )
+            })
+        }
+        NetworkRole::Client => {
+            let address = network_handler.join_address();
+            let resume_token = network_handler.resume_token.clone();
+
+            println!("Reconnecting to {}", address);
+            ConnectThread::spawn(move |cancel| {
+                networking::rejoin(
+                    &address,
+                    &name,
+                    resume_token.as_deref(),
+                    password.as_deref(),
+                    capture.as_deref(),
+                    cancel,
+                )
+            })
+        }
+    };
+
+    commands.insert_resource(Reconnection { connect });
+}
+
+/// Picks the game up where it stopped once the connection is back
+pub(crate) fn finish_reconnect(
+    mut commands: Commands,
+    reconnection: Res<Reconnection>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    let Some(result) = reconnection.connect.poll() else {
+        return;
+    };
+
+    match result {
+        Ok(Some(handshake)) => {
+            println!("Reconnected, resuming the game");
+            if let Err(e) = game_state.resume(&handshake.start, network_handler.role) {
+                network_handler.fail(e);
+                commands.remove_resource::<Reconnection>();
+                return;
+            }
+            network_handler.connection = Some(handshake.connection);
+            network_handler.opponent_name = handshake.opponent_name;
+            network_handler.resume_token = handshake.resume_token;
+            // it may have come back with another client
+            capabilities::exchange_capabilities(
+                &mut commands,
+                &mut network_handler,
+                handshake.peer_extensions,
+            );
+            network_handler.error = None;
+        }
+        Err(e) => network_handler.fail(e),
+        Ok(None) => {}
+    }
+
+    commands.remove_resource::<Reconnection>();
+}
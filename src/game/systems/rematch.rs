@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use vhultman_chess::Color as PieceColor;
+
+use crate::game::networking::Message;
+use crate::game::spectators::Spectators;
+use crate::game::{record, ClientGameState};
+use crate::general::resources::{HostColor, HostSettings, NetworkHandler, NetworkRole};
+
+/// Starts the next game over the same connection once both players asked for it. The client
+/// asks by sending a new `Start`, the host answers it with colors swapped like a handshake.
+pub(crate) fn start_rematch(
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+    host_settings: Res<HostSettings>,
+    spectators: Option<ResMut<Spectators>>,
+) {
+    if !game_state.is_game_over() || !game_state.rematch_requested {
+        return;
+    }
+    let Some(received) = game_state.rematch_start.take() else {
+        return;
+    };
+
+    let start = match network_handler.role {
+        NetworkRole::Server => {
+            let settings = HostSettings {
+                color: match game_state.own_color {
+                    PieceColor::White => HostColor::Black,
+                    PieceColor::Black => HostColor::White,
+                },
+                ..host_settings.clone()
+            };
+
+            let start = settings.start_packet(&network_handler.display_name());
+            network_handler.send(Message::start(start.clone()));
+            start
+        }
+        NetworkRole::Client => received.clone(),
+    };
+
+    if network_handler.error.is_some() {
+        return;
+    }
+    let new_game = match ClientGameState::from_start(&start, network_handler.role) {
+        Ok(new_game) => new_game,
+        Err(e) => {
+            network_handler.fail(e);
+            return;
+        }
+    };
+
+    println!("Starting a rematch");
+    record::report_saved(record::save_game_record(&game_state, &network_handler));
+
+    let spawned_pieces = game_state.spawned_pieces;
+    let chat = std::mem::take(&mut game_state.chat);
+    *game_state = new_game;
+    // the pieces stay, update_board replaces the ones that are out of place
+    game_state.spawned_pieces = spawned_pieces;
+    // it's still the same conversation
+    game_state.chat = chat;
+
+    network_handler.opponent_name = received.name;
+
+    if let Some(mut spectators) = spectators {
+        spectators.restart();
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 use vhultman_chess::Color as PieceColor;
 use vhultman_chess::Position;
@@ -59,7 +61,15 @@ pub(crate) fn setup(
 
     // Setup game state and more
     commands.insert_resource(ClientGameState {
+        start: chess_networking::Start {
+            is_white: true,
+            name: None,
+            fen: None,
+            time: None,
+            inc: None,
+        },
         board_state: Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap(),
+        history: Vec::new(),
         selected_piece: None,
         spawned_pieces: 0,
         board_dirty: true,
@@ -67,6 +77,18 @@ pub(crate) fn setup(
         pending_promotion_move: None,
         own_color: PieceColor::White,
         network_state: NetworkState::Normal,
-        next_ack_state: None,
+        server_color: PieceColor::White,
+        result: None,
+        offer_draw: false,
+        incoming_draw_offer: false,
+        outgoing_draw_offer: false,
+        clock: None,
+        opponent_silence: Duration::ZERO,
+        rematch_requested: false,
+        rematch_start: None,
+        chat: Vec::new(),
+        announced_capabilities: None,
+        takeback: None,
+        takebacks: 0,
     });
 }
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+
+use crate::game::{ClientGameState, NetworkState, SelfPlay};
+use crate::general::resources::NetworkHandler;
+
+use super::board;
+
+/// Runs the hidden side of a self-play game. It answers over the loopback like a remote player
+/// would, and once it's its turn the two sides trade places so the player moves for it.
+pub(crate) fn play_other_side(
+    mut self_play: ResMut<SelfPlay>,
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    let self_play = &mut *self_play;
+
+    if let Err(e) = board::receive_packets(&mut self_play.game_state, self_play.connection.as_mut())
+    {
+        network_handler.fail(e);
+        return;
+    }
+
+    let other_side_to_move = self_play.game_state.network_state == NetworkState::Normal
+        || self_play.game_state.incoming_draw_offer;
+
+    if game_state.network_state == NetworkState::Normal
+        || !other_side_to_move
+        || game_state.is_game_over()
+    {
+        return;
+    }
+
+    let Some(connection) = network_handler.connection.as_mut() else {
+        return;
+    };
+
+    std::mem::swap(&mut *game_state, &mut self_play.game_state);
+    std::mem::swap(connection, &mut self_play.connection);
+
+    // both sides have the same position now, but only the one that was shown knows what the
+    // 3d board looks like and how long the move took
+    let previous = &mut self_play.game_state;
+    game_state.board_dirty = std::mem::take(&mut previous.board_dirty);
+    game_state.last_move = previous.last_move;
+    game_state.spawned_pieces = previous.spawned_pieces;
+    game_state.clock = previous.clock.clone();
+    game_state.selected_piece = None;
+}
@@ -1,20 +1,16 @@
-use std::time::Duration;
-
 use bevy::prelude::*;
 use bevy_mod_picking::PickableBundle;
-use vhultman_chess::Color as PieceColor;
-use vhultman_chess::Position;
 
-use crate::game::NetworkState;
 use crate::{
     game::{
-        board_id_to_world_pos, networking::Connection, ChessSquare, ClientGameState, OnGameScreen,
-        PieceModelData, SquareResourceData,
+        board_id_to_world_pos, engine::EngineOpponent, record, spectators::Spectators, ChessSquare,
+        ClientGameState, HotSeat, OnGameScreen, PeerCapabilities, PieceModelData, SelfPlay,
+        SquareResourceData,
     },
-    general::resources::{NetworkHandler, NetworkRole},
+    general::resources::NetworkHandler,
 };
 
-use super::board;
+use super::{board, capabilities, reconnect::Reconnection};
 
 pub fn setup_game_scene(
     mut commands: Commands,
@@ -23,120 +19,29 @@ pub fn setup_game_scene(
     square_resource_data: Res<SquareResourceData>,
     mut game_state: ResMut<ClientGameState>,
     mut network_handler: ResMut<NetworkHandler>,
+    hot_seat: Option<Res<HotSeat>>,
 ) {
-    // TODO TEMPORARY MOVE THIS IT IS NOT PART OF SCENE
-    match network_handler.role {
-        NetworkRole::Server => {
-            network_handler.connection = Some(Connection::new_server("0.0.0.0:22022"));
-
-            if let Some(connection) = network_handler.connection.as_mut() {
-                let packet = chess_networking::Start::try_from(&connection.read() as &[u8])
-                    .expect("Bad packet");
-
-                println!(
-                    "Client with name {} connected",
-                    packet.name.unwrap_or("client".to_string())
-                );
-
-                let response_packet = chess_networking::Start {
-                    is_white: true,
-                    name: Some("Servermannen".to_string()),
-                    fen: Some(
-                        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
-                    ),
-                    time: None,
-                    inc: None,
-                };
-
-                let response_packet_bytes: Vec<u8> = response_packet.clone().try_into().unwrap();
-
-                *game_state = ClientGameState {
-                    board_state: Position::from_fen(response_packet.fen.unwrap().as_str())
-                        .expect("Failed to parse initial server fen string"),
-                    board_dirty: true,
-                    last_move: None,
-                    pending_promotion_move: None,
-                    selected_piece: None,
-                    spawned_pieces: 0,
-                    own_color: if response_packet.is_white {
-                        PieceColor::White
-                    } else {
-                        PieceColor::Black
-                    },
-                    network_state: NetworkState::Normal,
-                    next_ack_state: None,
-                };
-
-                connection.write(response_packet_bytes);
-            }
-        }
-        NetworkRole::Client => {
-            let mut address = "127.0.0.1:22022";
-            if let Some(addr) = &network_handler.address_to_join {
-                if !addr.is_empty() {
-                    address = addr;
-                }
-            }
-
-            network_handler.connection = Some(Connection::new_client(address));
-
-            if let Some(connection) = network_handler.connection.as_mut() {
-                let start: Vec<u8> = chess_networking::Start {
-                    is_white: false,
-                    name: Some("Klientmannen".to_string()),
-                    fen: None,
-                    time: None,
-                    inc: None,
-                }
-                .try_into()
-                .unwrap();
-
-                connection.write(start);
-
-                // wait for start packet from server
-                std::thread::sleep(Duration::from_secs(2));
-                let buf: Vec<u8>;
-                loop {
-                    let new_buf = connection.read();
-                    if !new_buf.is_empty() {
-                        buf = new_buf;
-                        break;
-                    }
-                    std::thread::sleep(Duration::from_secs(1));
-                }
-
-                let packet = chess_networking::Start::try_from(&buf as &[u8]).expect("Bad packet");
-
-                *game_state = ClientGameState {
-                    board_state: Position::from_fen(
-                        packet
-                            .fen
-                            .unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR".to_string())
-                            .as_str(),
-                    )
-                    .expect("Failed to parse initial server fen string"),
-                    board_dirty: true,
-                    last_move: None,
-                    pending_promotion_move: None,
-                    selected_piece: None,
-                    spawned_pieces: 0,
-                    own_color: if packet.is_white {
-                        PieceColor::Black
-                    } else {
-                        PieceColor::White
-                    },
-                    network_state: NetworkState::Normal,
-                    next_ack_state: None,
-                };
-            }
+    let new_game = match hot_seat.as_deref() {
+        // both players are right here, there's nobody to connect to
+        Some(hot_seat) => ClientGameState::hot_seat(&hot_seat.start),
+        None => {
+            // the handshake has already been done on the connecting screen
+            let start = network_handler
+                .start
+                .take()
+                .expect("Entered game without a finished handshake");
+
+            ClientGameState::from_start(&start, network_handler.role)
         }
+    };
+    match new_game {
+        Ok(new_game) => *game_state = new_game,
+        // shown like any other broken connection
+        Err(e) => network_handler.fail(e),
     }
 
-    game_state.network_state = if game_state.board_state.current_side() == game_state.own_color {
-        NetworkState::Normal
-    } else {
-        NetworkState::AwaitingMove
-    };
+    let peer_extensions = std::mem::take(&mut network_handler.peer_extensions);
+    capabilities::exchange_capabilities(&mut commands, &mut network_handler, peer_extensions);
 
     // camera
     commands.spawn((
@@ -204,3 +109,30 @@ pub fn setup_game_scene(
         }
     }
 }
+
+/// Drops the connection and the game when leaving so the next one starts fresh
+pub fn cleanup_game(
+    mut commands: Commands,
+    game_state: Option<Res<ClientGameState>>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    if let Some(game_state) = game_state {
+        record::report_saved(record::save_game_record(&game_state, &network_handler));
+    }
+
+    commands.remove_resource::<ClientGameState>();
+    commands.remove_resource::<SelfPlay>();
+    commands.remove_resource::<HotSeat>();
+    // stops the engine
+    commands.remove_resource::<EngineOpponent>();
+    // stops waiting for a reconnect
+    commands.remove_resource::<Reconnection>();
+    commands.remove_resource::<Spectators>();
+    commands.remove_resource::<PeerCapabilities>();
+    network_handler.connection = None;
+    network_handler.start = None;
+    network_handler.error = None;
+    network_handler.opponent_name = None;
+    network_handler.peer_extensions.clear();
+    network_handler.resume_token = None;
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::game::engine::EngineOpponent;
+use crate::game::networking::{Message, NetworkError};
+use crate::game::spectators::Spectators;
+use crate::game::{ClientGameState, HotSeat, NetworkState, SelfPlay};
+use crate::general::resources::{HostSettings, NetworkHandler, NetworkRole};
+
+use super::board;
+
+/// Run condition for the systems of a player, a spectator only watches
+pub(crate) fn spectating(network_handler: Res<NetworkHandler>) -> bool {
+    network_handler.spectating
+}
+
+/// Lets others watch the game the host is playing
+pub(crate) fn open_for_spectators(
+    mut commands: Commands,
+    network_handler: Res<NetworkHandler>,
+    host_settings: Res<HostSettings>,
+    self_play: Option<Res<SelfPlay>>,
+    engine: Option<Res<EngineOpponent>>,
+    hot_seat: Option<Res<HotSeat>>,
+) {
+    // a lobby game's host may not be reachable at all
+    if network_handler.role != NetworkRole::Server
+        || network_handler.lobby_address.is_some()
+        || self_play.is_some()
+        || engine.is_some()
+        || hot_seat.is_some()
+    {
+        return;
+    }
+
+    match Spectators::new(host_settings.bind_address) {
+        Ok(spectators) => {
+            if let Some(port) = spectators.port() {
+                println!("Spectators can watch on port {}", port);
+            }
+            commands.insert_resource(spectators);
+        }
+        Err(e) => println!("Failed to open the game for spectators: {}", e),
+    }
+}
+
+pub(crate) fn serve_spectators(
+    mut spectators: ResMut<Spectators>,
+    game_state: Res<ClientGameState>,
+    network_handler: Res<NetworkHandler>,
+) {
+    // our last move only counts once the opponent accepted it
+    let accepted = game_state.history.len()
+        - usize::from(game_state.network_state == NetworkState::AwaitingAck);
+
+    let opponent_name = network_handler
+        .opponent_name
+        .clone()
+        .unwrap_or("anonymous".to_string());
+    let (white, black) = if game_state.start.is_white {
+        (network_handler.display_name(), opponent_name)
+    } else {
+        (opponent_name, network_handler.display_name())
+    };
+
+    // spectators that join late couldn't tell what the clocks are at, so they don't get any
+    let start = chess_networking::Start {
+        name: Some(format!("{} vs {}", white, black)),
+        time: None,
+        inc: None,
+        ..game_state.start.clone()
+    };
+
+    spectators.update(
+        &start,
+        &game_state.history[..accepted],
+        game_state.takebacks,
+    );
+}
+
+/// Follows the game as a spectator, the host sends the moves of both sides
+pub(crate) fn watch_game(
+    mut game_state: ResMut<ClientGameState>,
+    mut network_handler: ResMut<NetworkHandler>,
+) {
+    let Some(connection) = network_handler.connection.as_mut() else {
+        return;
+    };
+
+    let result = loop {
+        match connection.read() {
+            Ok(Some(Message::Move(packet))) => {
+                if !board::apply_opponent_move(&mut game_state, &packet) {
+                    break Err(NetworkError::BadPacket);
+                }
+            }
+            // the players started a rematch or took moves back
+            Ok(Some(Message::Start(start, _))) => {
+                let spawned_pieces = game_state.spawned_pieces;
+                match ClientGameState::from_start(&start, NetworkRole::Client) {
+                    Ok(new_game) => *game_state = new_game,
+                    Err(e) => break Err(e),
+                }
+                game_state.spawned_pieces = spawned_pieces;
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    if let Err(e) = result {
+        network_handler.fail(e);
+    }
+}
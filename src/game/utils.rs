@@ -1,4 +1,6 @@
 use bevy::math::Vec3;
+use chess_networking::PromotionPiece;
+use vhultman_chess::{ChessMove, Color as PieceColor, PieceType, Position};
 
 pub fn world_pos_to_board_id(world_pos: Vec3) -> u32 {
     ((world_pos.z + 3.5) * 8.0 + world_pos.x + 3.5) as u32
@@ -11,3 +13,165 @@ pub fn board_id_to_world_pos(board_id: u32) -> Vec3 {
         (board_id / 8) as f32 - 3.5,
     )
 }
+
+pub fn other_color(color: PieceColor) -> PieceColor {
+    match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    }
+}
+
+pub fn color_name(color: PieceColor) -> &'static str {
+    match color {
+        PieceColor::White => "White",
+        PieceColor::Black => "Black",
+    }
+}
+
+/// The packet telling the opponent about a move, without forfeit or draw offer
+pub fn move_to_packet(m: ChessMove) -> chess_networking::Move {
+    chess_networking::Move {
+        from: (m.from() as u8 % 8, 7 - (m.from() as u8 / 8)),
+        to: (m.to() as u8 % 8, 7 - (m.to() as u8 / 8)),
+        promotion: if m.is_promotion() {
+            match m.promotion_piece() {
+                PieceType::Knight => Some(PromotionPiece::Knight),
+                PieceType::Bishop => Some(PromotionPiece::Bishop),
+                PieceType::Rook => Some(PromotionPiece::Rook),
+                PieceType::Queen => Some(PromotionPiece::Queen),
+                _ => None,
+            }
+        } else {
+            None
+        },
+        forfeit: false,
+        offer_draw: false,
+    }
+}
+
+/// A move in coordinate notation like "e2e4" or "e7e8q"
+pub fn move_name(m: ChessMove) -> String {
+    let promotion = if m.is_promotion() {
+        match m.promotion_piece() {
+            PieceType::Knight => "n",
+            PieceType::Bishop => "b",
+            PieceType::Rook => "r",
+            _ => "q",
+        }
+    } else {
+        ""
+    };
+
+    format!(
+        "{}{}{}",
+        board_id_to_square_name(m.from()),
+        board_id_to_square_name(m.to()),
+        promotion
+    )
+}
+
+/// Square name like "e4", board id 0 is a8
+pub fn board_id_to_square_name(board_id: u32) -> String {
+    format!(
+        "{}{}",
+        (b'a' + (board_id % 8) as u8) as char,
+        8 - board_id / 8
+    )
+}
+
+/// Board id of a square name like "e4", the inverse of `board_id_to_square_name`
+pub fn square_name_to_board_id(name: &str) -> Option<u32> {
+    let &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] = name.as_bytes() else {
+        return None;
+    };
+
+    Some((7 - u32::from(rank - b'1')) * 8 + u32::from(file - b'a'))
+}
+
+/// Builds a FEN string for the position. Castling rights are guessed from whether the kings and
+/// rooks are still on their starting squares, and the move counters aren't tracked.
+pub fn position_to_fen(position: &Position, last_move: Option<ChessMove>) -> String {
+    let mut fen = String::new();
+
+    for rank in 0..8 {
+        let mut empty = 0;
+        for file in 0..8 {
+            match position.piece_on(rank * 8 + file) {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+
+                    let c = match piece.t {
+                        PieceType::Pawn => 'p',
+                        PieceType::Rook => 'r',
+                        PieceType::Knight => 'n',
+                        PieceType::Bishop => 'b',
+                        PieceType::Queen => 'q',
+                        PieceType::King => 'k',
+                    };
+
+                    fen.push(if piece.color == PieceColor::White {
+                        c.to_ascii_uppercase()
+                    } else {
+                        c
+                    });
+                }
+                None => empty += 1,
+            }
+        }
+
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if rank < 7 {
+            fen.push('/');
+        }
+    }
+
+    fen.push_str(match position.current_side() {
+        PieceColor::White => " w ",
+        PieceColor::Black => " b ",
+    });
+
+    let is = |id: u32, t: PieceType, color: PieceColor| {
+        position
+            .piece_on(id)
+            .map_or(false, |piece| piece.t == t && piece.color == color)
+    };
+
+    let mut castling = String::new();
+    for (king, rook, color, c) in [
+        (60, 63, PieceColor::White, 'K'),
+        (60, 56, PieceColor::White, 'Q'),
+        (4, 7, PieceColor::Black, 'k'),
+        (4, 0, PieceColor::Black, 'q'),
+    ] {
+        if is(king, PieceType::King, color) && is(rook, PieceType::Rook, color) {
+            castling.push(c);
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+    fen.push_str(&castling);
+
+    // en passant square if the last move was a double pawn push
+    let en_passant = last_move.filter(|m| {
+        m.from().abs_diff(m.to()) == 16
+            && position
+                .piece_on(m.to())
+                .map_or(false, |piece| piece.t == PieceType::Pawn)
+    });
+
+    match en_passant {
+        Some(m) => fen.push_str(&format!(
+            " {} 0 1",
+            board_id_to_square_name((m.from() + m.to()) / 2)
+        )),
+        None => fen.push_str(" - 0 1"),
+    }
+
+    fen
+}
@@ -1,6 +1,13 @@
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
 use bevy::prelude::*;
 
-use crate::game::networking::Connection;
+use crate::game::{
+    networking::{Message, NetworkError, Transport, DEFAULT_PORT},
+    resources::{Difficulty, TimeControl},
+};
 
 #[derive(Resource)]
 pub struct SoundEffects {
@@ -18,9 +25,160 @@ pub enum NetworkRole {
     Client,
 }
 
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum HostColor {
+    #[default]
+    White,
+    Black,
+    Random,
+}
+
+/// What the host picked for the game, sent to the client in the `Start` response
+#[derive(Resource, Clone)]
+pub struct HostSettings {
+    /// Interface and port to listen on
+    pub bind_address: SocketAddr,
+    pub color: HostColor,
+    /// Custom starting position, the standard one if `None`
+    pub fen: Option<String>,
+    pub time_control: Option<TimeControl>,
+}
+
+impl Default for HostSettings {
+    fn default() -> Self {
+        HostSettings {
+            bind_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DEFAULT_PORT),
+            color: HostColor::default(),
+            fen: None,
+            time_control: None,
+        }
+    }
+}
+
+impl HostSettings {
+    /// Builds the server's `Start` packet, picking a color if it's random
+    pub fn start_packet(&self, name: &str) -> chess_networking::Start {
+        let is_white = match self.color {
+            HostColor::White => true,
+            HostColor::Black => false,
+            // RandomState is seeded randomly, good enough for a coin flip
+            HostColor::Random => {
+                std::collections::hash_map::RandomState::new()
+                    .build_hasher()
+                    .finish()
+                    % 2
+                    == 0
+            }
+        };
+
+        chess_networking::Start {
+            is_white,
+            name: Some(name.to_string()),
+            fen: Some(
+                self.fen.clone().unwrap_or(
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                ),
+            ),
+            time: self.time_control.map(|time_control| time_control.time),
+            inc: self.time_control.map(|time_control| time_control.inc),
+        }
+    }
+}
+
+/// The engines to play against and how hard they try
+#[derive(Resource, Clone)]
+pub struct EngineSettings {
+    /// Path to the UCI engine executable, like Stockfish
+    pub path: String,
+    /// The UCI engine's `Skill Level` option, 0 to 20
+    pub skill: u8,
+    /// How long the UCI engine thinks about each move
+    pub think_time: Duration,
+    /// How hard the built-in computer plays
+    pub difficulty: Difficulty,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        EngineSettings {
+            path: String::new(),
+            skill: 10,
+            think_time: Duration::from_secs(1),
+            difficulty: Difficulty::default(),
+        }
+    }
+}
+
+/// Options for local hot-seat games
+#[derive(Resource, Clone, Default)]
+pub struct LocalSettings {
+    /// Turn the camera to the side to move after each move
+    pub rotate_camera: bool,
+}
+
 #[derive(Resource)]
 pub struct NetworkHandler {
-    pub connection: Option<Connection>,
+    /// The tcp connection to the opponent, or one end of a loopback when playing yourself
+    pub connection: Option<Box<dyn Transport>>,
     pub role: NetworkRole,
     pub address_to_join: Option<String>,
+    /// Our display name, sent in the `Start` packet
+    pub player_name: String,
+    pub opponent_name: Option<String>,
+    /// The extensions the opponent listed in its `Start`, handed to `PeerCapabilities` when
+    /// the game starts
+    pub peer_extensions: Vec<String>,
+    /// Proves it's us, or tells the opponent apart from strangers, when the game is resumed
+    pub resume_token: Option<String>,
+    /// The `Start` packet sent by the server during the handshake
+    pub start: Option<chess_networking::Start>,
+    /// Set when the connection broke, the game ui shows it to the player
+    pub error: Option<NetworkError>,
+    /// Write all packets of the next connection to a capture file
+    pub record_traffic: bool,
+    /// Watching someone else's game instead of playing, only used with the client role
+    pub spectating: bool,
+    /// Play through the lobby server at this address instead of connecting directly
+    pub lobby_address: Option<String>,
+    /// The lobby game to join, only used with the client role
+    pub lobby_game: Option<u32>,
+    /// The game password, required from clients when hosting and sent to the host when joining
+    pub password: Option<String>,
+}
+
+impl NetworkHandler {
+    /// Sends a packet to the opponent, if that fails the connection is dropped
+    pub fn send(&mut self, message: Message) {
+        if let Some(connection) = self.connection.as_mut() {
+            if let Err(e) = connection.write(message) {
+                self.fail(e);
+            }
+        }
+    }
+
+    /// The name sent in our `Start` packet, a default one if the player didn't pick any
+    pub fn display_name(&self) -> String {
+        if self.player_name.is_empty() {
+            match self.role {
+                NetworkRole::Server => "Servermannen".to_string(),
+                NetworkRole::Client => "Klientmannen".to_string(),
+            }
+        } else {
+            self.player_name.clone()
+        }
+    }
+
+    /// Where the client connects to, localhost if nothing was entered
+    pub fn join_address(&self) -> String {
+        self.address_to_join
+            .clone()
+            .filter(|addr| !addr.is_empty())
+            .unwrap_or("127.0.0.1:22022".to_string())
+    }
+
+    pub fn fail(&mut self, error: NetworkError) {
+        println!("Network error: {}", error);
+        self.connection = None;
+        self.error = Some(error);
+    }
 }
@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 
-use super::resources::{NetworkHandler, NetworkRole, SoundEffects};
+use super::resources::{
+    EngineSettings, HostSettings, LocalSettings, NetworkHandler, NetworkRole, SoundEffects,
+};
 
 pub(crate) fn setup_resources(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(SoundEffects {
@@ -16,5 +18,20 @@ pub(crate) fn setup_resources(mut commands: Commands, asset_server: Res<AssetSer
         connection: None,
         role: NetworkRole::Client,
         address_to_join: None,
+        player_name: String::new(),
+        opponent_name: None,
+        peer_extensions: Vec::new(),
+        resume_token: None,
+        start: None,
+        error: None,
+        record_traffic: false,
+        spectating: false,
+        lobby_address: None,
+        lobby_game: None,
+        password: None,
     });
+
+    commands.insert_resource(HostSettings::default());
+    commands.insert_resource(EngineSettings::default());
+    commands.insert_resource(LocalSettings::default());
 }
@@ -0,0 +1,427 @@
+//! A headless server players connect to instead of to each other, so only the lobby has to be
+//! reachable. Players either open a game or join one from the list, after that the lobby relays
+//! the bytes between the two unchanged and the game runs like over a direct connection.
+//!
+//! Before a game is paired everything sent is a `LobbyMessage`, see `src/game/lobby.rs`.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::game::{
+    extension::Extension,
+    lobby::{LobbyMessage, OpenGame, LOBBY_PORT},
+    networking::{Message, MessageBuffer},
+};
+
+/// Where the lobby listens when nothing else is given
+pub fn default_address() -> String {
+    format!("0.0.0.0:{}", LOBBY_PORT)
+}
+
+enum ClientState {
+    /// Connected, may list the games before hosting or joining one
+    Idle,
+    Hosting(OpenGame),
+    /// Paired, everything it sends goes to the client with this id
+    Playing(u64),
+}
+
+struct Client {
+    id: u64,
+    address: SocketAddr,
+    stream: TcpStream,
+    buffer: MessageBuffer,
+    /// Bytes the socket hasn't taken yet
+    outgoing: Vec<u8>,
+    state: ClientState,
+    /// Dropped once `outgoing` is written, after refusing it or losing its opponent
+    closing: bool,
+    closed: bool,
+}
+
+impl Client {
+    fn send(&mut self, message: LobbyMessage) {
+        match Message::Extension(Extension::Lobby(message)).encode() {
+            Ok(bytes) => self.outgoing.extend_from_slice(&bytes),
+            Err(e) => println!("Failed to encode lobby message: {}", e),
+        }
+    }
+
+    fn refuse(&mut self, reason: &str) {
+        println!("Refused {}: {}", self.address, reason);
+        self.send(LobbyMessage::Refused(reason.to_string()));
+        self.closing = true;
+    }
+
+    /// Everything that arrived since the last call
+    fn receive(&mut self) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 2048];
+
+        while !self.closed {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(len) => received.extend_from_slice(&buf[..len]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => self.closed = true,
+            }
+        }
+
+        received
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() && !self.closed {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.closed = true,
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => self.closed = true,
+            }
+        }
+
+        if self.closing && self.outgoing.is_empty() {
+            let _ = self.stream.shutdown(Shutdown::Both);
+            self.closed = true;
+        }
+    }
+}
+
+pub struct LobbyServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    next_client: u64,
+    next_game: u32,
+}
+
+impl LobbyServer {
+    pub fn bind(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(LobbyServer {
+            listener,
+            clients: Vec::new(),
+            next_client: 0,
+            next_game: 1,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves players until `stop` gets set
+    pub fn run(&mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Does everything that can be done without waiting
+    fn poll(&mut self) {
+        self.accept();
+
+        for i in 0..self.clients.len() {
+            let received = self.clients[i].receive();
+            if !received.is_empty() {
+                self.handle(i, &received);
+            }
+        }
+
+        // a game can't go on without both players
+        let abandoned: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|client| client.closed)
+            .filter_map(|client| match client.state {
+                ClientState::Playing(peer) => Some(peer),
+                _ => None,
+            })
+            .collect();
+        for peer in abandoned {
+            if let Some(peer) = self.index_of(peer) {
+                self.clients[peer].closing = true;
+            }
+        }
+
+        for client in &mut self.clients {
+            client.flush();
+        }
+
+        self.clients.retain(|client| {
+            if client.closed {
+                println!("{} left", client.address);
+            }
+            !client.closed
+        });
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        println!("Failed to set up connection from {}: {}", address, e);
+                        continue;
+                    }
+
+                    println!("{} connected", address);
+                    self.clients.push(Client {
+                        id: self.next_client,
+                        address,
+                        stream,
+                        buffer: MessageBuffer::default(),
+                        outgoing: Vec::new(),
+                        state: ClientState::Idle,
+                        closing: false,
+                        closed: false,
+                    });
+                    self.next_client += 1;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    println!("Failed to accept connection: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn index_of(&self, id: u64) -> Option<usize> {
+        self.clients.iter().position(|client| client.id == id)
+    }
+
+    fn open_games(&self) -> Vec<OpenGame> {
+        self.clients
+            .iter()
+            .filter(|client| !client.closing)
+            .filter_map(|client| match &client.state {
+                ClientState::Hosting(game) => Some(game.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Relays what a paired client sent, or goes through the lobby messages of one that isn't
+    fn handle(&mut self, i: usize, received: &[u8]) {
+        if self.clients[i].closing {
+            return;
+        }
+
+        if let ClientState::Playing(peer) = self.clients[i].state {
+            if let Some(peer) = self.index_of(peer) {
+                self.clients[peer].outgoing.extend_from_slice(received);
+            }
+            return;
+        }
+
+        self.clients[i].buffer.push(received);
+
+        loop {
+            let message = match self.clients[i].buffer.next_frame() {
+                Ok(Some(frame)) => Message::decode(&frame),
+                Ok(None) => return,
+                Err(_) => None,
+            };
+
+            let Some(Message::Extension(Extension::Lobby(message))) = message else {
+                self.clients[i].refuse("This is a lobby server, host or join a game first");
+                return;
+            };
+
+            let idle = matches!(self.clients[i].state, ClientState::Idle);
+
+            match message {
+                LobbyMessage::List => {
+                    let games = self.open_games();
+                    self.clients[i].send(LobbyMessage::Games(games));
+                }
+                LobbyMessage::Host {
+                    name,
+                    time,
+                    inc,
+                    host_is_white,
+                } if idle => {
+                    let id = self.next_game;
+                    self.next_game += 1;
+
+                    println!("{} opened game {}", name, id);
+                    let client = &mut self.clients[i];
+                    client.state = ClientState::Hosting(OpenGame {
+                        id,
+                        host: name,
+                        time,
+                        inc,
+                        host_is_white,
+                    });
+                    client.send(LobbyMessage::Hosted { id });
+                }
+                LobbyMessage::Join { id, name } if idle => {
+                    let host = self.clients.iter().position(|client| {
+                        !client.closing
+                            && matches!(&client.state, ClientState::Hosting(game) if game.id == id)
+                    });
+                    let Some(host) = host else {
+                        self.clients[i].refuse("That game isn't open anymore");
+                        return;
+                    };
+
+                    let ClientState::Hosting(game) = &self.clients[host].state else {
+                        return;
+                    };
+                    println!("{} joined {}'s game {}", name, game.host, id);
+
+                    let host_name = game.host.clone();
+                    let (host_id, guest_id) = (self.clients[host].id, self.clients[i].id);
+                    self.clients[host].state = ClientState::Playing(guest_id);
+                    self.clients[host].send(LobbyMessage::Paired { opponent: name });
+                    self.clients[i].state = ClientState::Playing(host_id);
+                    self.clients[i].send(LobbyMessage::Paired {
+                        opponent: host_name,
+                    });
+
+                    // anything sent right after joining is already the game, the bytes go on as
+                    // they came so a frame that's only partly here arrives whole
+                    let rest = self.clients[i].buffer.take_rest();
+                    self.clients[host].outgoing.extend_from_slice(&rest);
+                    return;
+                }
+                _ => {
+                    self.clients[i].refuse("Already hosting or playing a game");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use super::*;
+    use crate::game::{
+        lobby,
+        networking::{NetworkError, Transport},
+    };
+    use crate::general::resources::HostSettings;
+
+    #[test]
+    fn pairs_players_and_relays_their_game() {
+        let mut server = LobbyServer::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || server.run(&stop))
+        };
+
+        let cancel = AtomicBool::new(false);
+        let settings = HostSettings::default();
+
+        let host = {
+            let address = address.clone();
+            let settings = settings.clone();
+            std::thread::spawn(move || {
+                lobby::establish(
+                    &address,
+                    None,
+                    "Servermannen",
+                    &settings,
+                    None,
+                    &AtomicBool::new(false),
+                )
+            })
+        };
+
+        // the host shows up once its request went through
+        let games = loop {
+            let games = lobby::list_games(&address, &cancel).unwrap().unwrap();
+            if !games.is_empty() {
+                break games;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].host, "Servermannen");
+        assert_eq!(games[0].host_is_white, Some(true));
+
+        let mut client = lobby::establish(
+            &address,
+            Some(games[0].id),
+            "Klientmannen",
+            &settings,
+            None,
+            &cancel,
+        )
+        .unwrap()
+        .unwrap();
+        let mut host = host.join().unwrap().unwrap().unwrap();
+
+        assert_eq!(client.opponent_name.as_deref(), Some("Servermannen"));
+        assert_eq!(host.opponent_name.as_deref(), Some("Klientmannen"));
+        assert!(client.start.is_white && host.start.is_white);
+
+        // a paired game isn't open anymore
+        assert!(lobby::list_games(&address, &cancel)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+
+        host.connection
+            .write(Message::Move(chess_networking::Move {
+                from: (4, 1),
+                to: (4, 3),
+                promotion: None,
+                forfeit: false,
+                offer_draw: false,
+            }))
+            .unwrap();
+        let received = client.connection.read_blocking(&cancel).unwrap();
+        assert!(matches!(
+            received,
+            Some(Message::Move(packet)) if packet.from == (4, 1) && packet.to == (4, 3)
+        ));
+
+        // the lobby hangs up on the other player when one leaves
+        drop(host);
+        assert!(client.connection.read_blocking(&cancel).is_err());
+
+        stop.store(true, Ordering::Relaxed);
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn joining_a_missing_game_is_refused() {
+        let mut server = LobbyServer::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || server.run(&stop))
+        };
+
+        let result = lobby::establish(
+            &address,
+            Some(7),
+            "Klientmannen",
+            &HostSettings::default(),
+            None,
+            &AtomicBool::new(false),
+        );
+        assert!(matches!(result, Err(NetworkError::Refused(_))));
+
+        stop.store(true, Ordering::Relaxed);
+        server_thread.join().unwrap();
+    }
+}
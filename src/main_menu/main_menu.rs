@@ -1,8 +1,25 @@
+use std::{
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    time::Duration,
+};
+
 use bevy::prelude::*;
-use bevy_simple_text_input::{TextInputBundle, TextInputValue};
+use bevy_simple_text_input::{TextInputBundle, TextInputInactive, TextInputValue};
+use vhultman_chess::Position;
 
 use crate::{
-    general::resources::{NetworkHandler, NetworkRole, SoundEffects},
+    game::{
+        computer::Computer,
+        discovery::{self, Announcement, ANNOUNCE_TIMEOUT},
+        engine::{engine_name, EngineOpponent},
+        lobby::{self, OpenGame},
+        networking::{ConnectThread, Loopback, DEFAULT_PORT},
+        resources::{ClientGameState, Difficulty, HotSeat, SelfPlay, TimeControl},
+    },
+    general::resources::{
+        EngineSettings, HostColor, HostSettings, LocalSettings, NetworkHandler, NetworkRole,
+        SoundEffects,
+    },
     GameState,
 };
 
@@ -11,16 +28,302 @@ use super::OnMainMenuScreen;
 #[derive(Copy, Clone, PartialEq, Component, Debug)]
 pub(crate) enum MenuAction {
     Host,
+    StartHosting,
+    Back,
     Join,
+    /// Join a hosted game as a read-only spectator
+    Spectate,
+    /// Join a game found on the local network
+    JoinDiscovered(SocketAddr),
+    /// Open the lobby panel and list the lobby's games
+    Lobby,
+    /// List the games of the lobby at the address typed in
+    ConnectLobby,
+    /// Open a game in the lobby and wait there for an opponent
+    HostInLobby,
+    JoinLobbyGame(u32),
+    /// Open the panel for playing against a UCI engine
+    Engine,
+    /// Start the engine and a game against it
+    PlayEngine,
+    EngineSkill,
+    EngineThinkTime,
+    /// Open the panel for playing against the built-in computer
+    Computer,
+    /// Start a game against the built-in computer
+    PlayComputer,
+    Difficulty,
+    /// Open the panel for a local game at this window
+    Local,
+    /// Start a local game, no network involved
+    PlayLocal,
+    RotateCamera,
+    /// Developer mode, play both sides over an in-process connection
+    SelfPlay,
+    RecordTraffic,
+    TimeControl,
+    Color,
+}
+
+/// The panels of the menu, only one is shown at a time
+#[derive(Copy, Clone, PartialEq, Component, Debug)]
+pub(crate) enum MenuPanel {
+    Main,
+    Host,
+    Lobby,
+    Engine,
+    Computer,
+    Local,
+}
+
+#[derive(Copy, Clone, PartialEq, Component, Debug)]
+pub(crate) enum MenuInput {
+    Name,
+    Fen,
+    Interface,
+    Port,
+    JoinAddress,
+    LobbyAddress,
+    Password,
+    EnginePath,
+}
+
+/// Texts that change while the menu is open
+#[derive(Copy, Clone, PartialEq, Component, Debug)]
+pub(crate) enum MenuText {
+    TimeControl,
+    Color,
+    HostError,
+    RecordTraffic,
+    EngineSkill,
+    EngineThinkTime,
+    EngineError,
+    Difficulty,
+    RotateCamera,
+}
+
+/// The node holding one button per game found on the local network
+#[derive(Component)]
+pub(crate) struct GameList;
+
+/// The node holding one button per open game in the lobby
+#[derive(Component)]
+pub(crate) struct LobbyGameList;
+
+/// How often the lobby's games are asked for again while the lobby panel is open
+const LOBBY_REFRESH: f32 = 2.0;
+
+/// The open games of a lobby, kept up to date while the lobby panel is open. Removing it
+/// cancels a refresh that's still running.
+#[derive(Resource)]
+pub(crate) struct LobbyBrowser {
+    address: String,
+    refresh: Option<ConnectThread<Vec<OpenGame>>>,
+    /// `None` until the lobby answered the first time
+    games: Option<Result<Vec<OpenGame>, String>>,
+    last_refresh: Option<f32>,
+    /// Set when the list changed and the buttons need to be rebuilt
+    dirty: bool,
+}
+
+impl LobbyBrowser {
+    fn new(address: String) -> Self {
+        LobbyBrowser {
+            address,
+            refresh: None,
+            games: None,
+            last_refresh: None,
+            dirty: true,
+        }
+    }
+}
+
+/// A game announced on the local network
+pub(crate) struct LanGame {
+    address: SocketAddr,
+    announcement: Announcement,
+    last_seen: f32,
+}
+
+/// Games found on the local network while the menu is open
+#[derive(Resource)]
+pub(crate) struct LanGames {
+    listener: Result<discovery::Listener, String>,
+    games: Vec<LanGame>,
+    /// Set when the list changed and the buttons need to be rebuilt
+    dirty: bool,
+}
+
+/// The time controls the host can cycle through, in seconds
+const TIME_CONTROLS: [Option<TimeControl>; 6] = [
+    None,
+    Some(TimeControl { time: 60, inc: 0 }),
+    Some(TimeControl { time: 180, inc: 2 }),
+    Some(TimeControl { time: 300, inc: 0 }),
+    Some(TimeControl { time: 600, inc: 5 }),
+    Some(TimeControl { time: 900, inc: 10 }),
+];
+
+fn time_control_label(time_control: Option<TimeControl>) -> String {
+    match time_control {
+        Some(time_control) => format!("Time control: {}", time_control),
+        None => "Time control: none".to_string(),
+    }
+}
+
+/// The engine skill levels and think times the player can cycle through
+const ENGINE_SKILLS: [u8; 5] = [0, 5, 10, 15, 20];
+const ENGINE_THINK_TIMES: [Duration; 4] = [
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+];
+
+/// The difficulties of the built-in computer, easiest first
+const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Beginner,
+    Difficulty::Easy,
+    Difficulty::Medium,
+    Difficulty::Hard,
+];
+
+/// The name the built-in computer plays under
+const COMPUTER_NAME: &str = "Computer";
+
+fn difficulty_label(difficulty: Difficulty) -> String {
+    format!(
+        "Difficulty: {}",
+        match difficulty {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    )
+}
+
+fn engine_skill_label(skill: u8) -> String {
+    format!("Skill level: {}", skill)
+}
+
+fn engine_think_time_label(think_time: Duration) -> String {
+    format!("Think time: {:?}", think_time)
+}
+
+fn rotate_camera_label(rotate_camera: bool) -> String {
+    format!(
+        "Turn board to the side to move: {}",
+        if rotate_camera { "on" } else { "off" }
+    )
+}
+
+fn record_traffic_label(record_traffic: bool) -> String {
+    format!(
+        "Record traffic: {}",
+        if record_traffic { "on" } else { "off" }
+    )
+}
+
+fn color_label(color: HostColor) -> String {
+    format!(
+        "Play as: {}",
+        match color {
+            HostColor::White => "White",
+            HostColor::Black => "Black",
+            HostColor::Random => "Random",
+        }
+    )
 }
 
-const BUTTON_COLOR: Color = Color::srgb(100.0 / 255.0, 100.0 / 255.0, 100.0 / 255.0);
-const BUTTON_HOVER_COLOR: Color = Color::srgb(150.0 / 255.0, 150.0 / 255.0, 150.0 / 255.0);
+pub(crate) const BUTTON_COLOR: Color = Color::srgb(100.0 / 255.0, 100.0 / 255.0, 100.0 / 255.0);
+pub(crate) const BUTTON_HOVER_COLOR: Color =
+    Color::srgb(150.0 / 255.0, 150.0 / 255.0, 150.0 / 255.0);
 
-pub(crate) fn menu_setup(mut commands: Commands) {
+fn text_input(
+    input: MenuInput,
+    width: f32,
+    value: &str,
+    placeholder: &str,
+) -> (NodeBundle, TextInputBundle, Interaction, MenuInput) {
+    (
+        NodeBundle {
+            style: Style {
+                width: Val::Px(width),
+                padding: UiRect::all(Val::Px(5.0)),
+                display: Display::Flex,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            border_radius: BorderRadius::all(Val::Px(6.0)),
+            background_color: Srgba::rgb_u8(100, 100, 100).into(),
+            ..default()
+        },
+        TextInputBundle::default()
+            .with_text_style(TextStyle { ..default() })
+            .with_value(value)
+            .with_placeholder(placeholder, None)
+            .with_inactive(true),
+        Interaction::None,
+        input,
+    )
+}
+
+pub(crate) fn menu_setup(
+    mut commands: Commands,
+    host_settings: Res<HostSettings>,
+    network_handler: Res<NetworkHandler>,
+    engine_settings: Res<EngineSettings>,
+    local_settings: Res<LocalSettings>,
+) {
     // general setup
     commands.spawn((Camera2dBundle::default(), OnMainMenuScreen));
 
+    commands.insert_resource(LanGames {
+        listener: discovery::Listener::new().map_err(|e| e.to_string()),
+        games: Vec::new(),
+        dirty: true,
+    });
+
+    let panel_bundle = NodeBundle {
+        style: Style {
+            padding: UiRect::all(Val::Px(32.0)),
+            display: Display::Flex,
+            align_items: AlignItems::Center,
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(16.0),
+            ..Default::default()
+        },
+        border_radius: BorderRadius::all(Val::Px(12.0)),
+        background_color: Srgba::rgb_u8(50, 50, 50).into(),
+        ..Default::default()
+    };
+
+    let button_bundle = ButtonBundle {
+        style: Style {
+            width: Val::Px(326.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            display: Display::Flex,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        border_radius: BorderRadius::all(Val::Px(6.0)),
+        background_color: BUTTON_COLOR.into(),
+        ..default()
+    };
+
+    let row_bundle = NodeBundle {
+        style: Style {
+            display: Display::Flex,
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(6.0),
+            ..default()
+        },
+        ..default()
+    };
+
     // ui setup
     commands
         .spawn((
@@ -39,19 +342,7 @@ pub(crate) fn menu_setup(mut commands: Commands) {
         .with_children(|parent| {
             // menu panel
             parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        padding: UiRect::all(Val::Px(32.0)),
-                        display: Display::Flex,
-                        align_items: AlignItems::Center,
-                        flex_direction: FlexDirection::Column,
-                        row_gap: Val::Px(16.0),
-                        ..Default::default()
-                    },
-                    border_radius: BorderRadius::all(Val::Px(12.0)),
-                    background_color: Srgba::rgb_u8(50, 50, 50).into(),
-                    ..Default::default()
-                })
+                .spawn((panel_bundle.clone(), MenuPanel::Main))
                 .with_children(|parent| {
                     // title
                     parent.spawn(TextBundle::from_section(
@@ -62,19 +353,30 @@ pub(crate) fn menu_setup(mut commands: Commands) {
                         },
                     ));
 
-                    let button_bundle = ButtonBundle {
-                        style: Style {
-                            width: Val::Px(326.0),
-                            padding: UiRect::all(Val::Px(8.0)),
-                            display: Display::Flex,
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        border_radius: BorderRadius::all(Val::Px(6.0)),
-                        background_color: BUTTON_COLOR.into(),
-                        ..default()
-                    };
+                    // name, used both when hosting and joining
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Name", TextStyle { ..default() }));
+                        parent.spawn(text_input(
+                            MenuInput::Name,
+                            280.0,
+                            &network_handler.player_name,
+                            "Your name",
+                        ));
+                    });
+
+                    // asked from whoever joins our game, and sent when we join one
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "Password",
+                            TextStyle { ..default() },
+                        ));
+                        parent.spawn(text_input(
+                            MenuInput::Password,
+                            280.0,
+                            network_handler.password.as_deref().unwrap_or(""),
+                            "Game password (optional)",
+                        ));
+                    });
 
                     // host button
                     parent
@@ -97,86 +399,878 @@ pub(crate) fn menu_setup(mut commands: Commands) {
                         });
 
                     // join area
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        // text field
+                        parent.spawn(text_input(
+                            MenuInput::JoinAddress,
+                            184.0,
+                            network_handler.address_to_join.as_deref().unwrap_or(""),
+                            "host[:port] or [IPv6]:port",
+                        ));
+
+                        // button
+                        parent
+                            .spawn((
+                                {
+                                    let mut bundle = button_bundle.clone();
+                                    bundle.style.width = Val::Px(64.0);
+                                    bundle
+                                },
+                                MenuAction::Join,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Join",
+                                    TextStyle { ..default() },
+                                ));
+                            });
+
+                        parent
+                            .spawn((
+                                {
+                                    let mut bundle = button_bundle.clone();
+                                    bundle.style.width = Val::Px(96.0);
+                                    bundle
+                                },
+                                MenuAction::Spectate,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Spectate",
+                                    TextStyle { ..default() },
+                                ));
+                            });
+                    });
+
+                    // games through a lobby server, for when neither player is reachable
                     parent
-                        .spawn(NodeBundle {
+                        .spawn((button_bundle.clone(), MenuAction::Lobby))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Connect to lobby",
+                                TextStyle { ..default() },
+                            ));
+                        });
+
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::Computer))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Play vs computer",
+                                TextStyle { ..default() },
+                            ));
+                        });
+
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::Engine))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Play against an engine",
+                                TextStyle { ..default() },
+                            ));
+                        });
+
+                    // two players taking turns at this window
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::Local))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Local game",
+                                TextStyle { ..default() },
+                            ));
+                        });
+
+                    // writes a capture file for debugging, see mock_peer for decoding it
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::RecordTraffic))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    record_traffic_label(network_handler.record_traffic),
+                                    TextStyle { ..default() },
+                                ),
+                                MenuText::RecordTraffic,
+                            ));
+                        });
+
+                    if cfg!(debug_assertions) {
+                        parent
+                            .spawn((button_bundle.clone(), MenuAction::SelfPlay))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Play against yourself",
+                                    TextStyle { ..default() },
+                                ));
+                            });
+                    }
+
+                    // games on the local network, filled in by discover_games
+                    parent.spawn((
+                        NodeBundle {
                             style: Style {
+                                width: Val::Px(326.0),
                                 display: Display::Flex,
-                                flex_direction: FlexDirection::Row,
-                                column_gap: Val::Px(6.0),
+                                flex_direction: FlexDirection::Column,
+                                row_gap: Val::Px(6.0),
                                 ..default()
                             },
                             ..default()
-                        })
+                        },
+                        GameList,
+                    ));
+                });
+
+            // host setup panel
+            parent
+                .spawn((
+                    {
+                        let mut panel = panel_bundle.clone();
+                        panel.style.display = Display::None;
+                        panel
+                    },
+                    MenuPanel::Host,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Host game",
+                        TextStyle {
+                            font_size: 42.0,
+                            ..default()
+                        },
+                    ));
+
+                    // color button, cycles white, black and random
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::Color))
                         .with_children(|parent| {
-                            // text field
                             parent.spawn((
-                                NodeBundle {
-                                    style: Style {
-                                        width: Val::Px(256.0),
-                                        padding: UiRect::all(Val::Px(5.0)),
-                                        display: Display::Flex,
-                                        align_items: AlignItems::Center,
-                                        ..default()
+                                TextBundle::from_section(
+                                    color_label(host_settings.color),
+                                    TextStyle { ..default() },
+                                ),
+                                MenuText::Color,
+                            ));
+                        });
+
+                    // time control button, cycles through the presets
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::TimeControl))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    time_control_label(host_settings.time_control),
+                                    TextStyle { ..default() },
+                                ),
+                                MenuText::TimeControl,
+                            ));
+                        });
+
+                    // interface and port to listen on
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        parent.spawn(text_input(
+                            MenuInput::Interface,
+                            230.0,
+                            &host_settings.bind_address.ip().to_string(),
+                            "Interface",
+                        ));
+                        parent.spawn(text_input(
+                            MenuInput::Port,
+                            90.0,
+                            &host_settings.bind_address.port().to_string(),
+                            "Port",
+                        ));
+                    });
+
+                    // custom starting position
+                    parent.spawn(text_input(
+                        MenuInput::Fen,
+                        326.0,
+                        host_settings.fen.as_deref().unwrap_or(""),
+                        "Starting FEN (optional)",
+                    ));
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(255, 120, 120),
+                                ..default()
+                            },
+                        ),
+                        MenuText::HostError,
+                    ));
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        for (action, label) in [
+                            (MenuAction::Back, "Back"),
+                            (MenuAction::StartHosting, "Start hosting"),
+                        ] {
+                            parent
+                                .spawn((
+                                    {
+                                        let mut bundle = button_bundle.clone();
+                                        bundle.style.width = Val::Px(160.0);
+                                        bundle
                                     },
-                                    border_radius: BorderRadius::all(Val::Px(6.0)),
-                                    background_color: Srgba::rgb_u8(100, 100, 100).into(),
-                                    ..default()
+                                    action,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        label,
+                                        TextStyle { ..default() },
+                                    ));
+                                });
+                        }
+                    });
+                });
+
+            // lobby panel
+            parent
+                .spawn((
+                    {
+                        let mut panel = panel_bundle.clone();
+                        panel.style.display = Display::None;
+                        panel
+                    },
+                    MenuPanel::Lobby,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Lobby",
+                        TextStyle {
+                            font_size: 42.0,
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        parent.spawn(text_input(
+                            MenuInput::LobbyAddress,
+                            216.0,
+                            network_handler.lobby_address.as_deref().unwrap_or(""),
+                            "lobby host[:port]",
+                        ));
+
+                        parent
+                            .spawn((
+                                {
+                                    let mut bundle = button_bundle.clone();
+                                    bundle.style.width = Val::Px(104.0);
+                                    bundle
                                 },
-                                TextInputBundle::default()
-                                    .with_text_style(TextStyle { ..default() }),
+                                MenuAction::ConnectLobby,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Connect",
+                                    TextStyle { ..default() },
+                                ));
+                            });
+                    });
+
+                    // filled in by browse_lobby
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Px(326.0),
+                                display: Display::Flex,
+                                flex_direction: FlexDirection::Column,
+                                row_gap: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        LobbyGameList,
+                    ));
+
+                    // the same settings as on the host panel, lobby games always start from the
+                    // standard position
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::Color))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    color_label(host_settings.color),
+                                    TextStyle { ..default() },
+                                ),
+                                MenuText::Color,
                             ));
+                        });
 
-                            // button
+                    parent
+                        .spawn((button_bundle.clone(), MenuAction::TimeControl))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    time_control_label(host_settings.time_control),
+                                    TextStyle { ..default() },
+                                ),
+                                MenuText::TimeControl,
+                            ));
+                        });
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        for (action, label) in [
+                            (MenuAction::Back, "Back"),
+                            (MenuAction::HostInLobby, "Host here"),
+                        ] {
                             parent
                                 .spawn((
                                     {
                                         let mut bundle = button_bundle.clone();
-                                        bundle.style.width = Val::Px(64.0);
+                                        bundle.style.width = Val::Px(160.0);
                                         bundle
                                     },
-                                    MenuAction::Join,
+                                    action,
                                 ))
                                 .with_children(|parent| {
                                     parent.spawn(TextBundle::from_section(
-                                        "Join",
+                                        label,
                                         TextStyle { ..default() },
                                     ));
                                 });
-                        });
+                        }
+                    });
+                });
+
+            // engine panel, the engine plays the other side with the host settings
+            parent
+                .spawn((
+                    {
+                        let mut panel = panel_bundle.clone();
+                        panel.style.display = Display::None;
+                        panel
+                    },
+                    MenuPanel::Engine,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Engine",
+                        TextStyle {
+                            font_size: 42.0,
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn(text_input(
+                        MenuInput::EnginePath,
+                        326.0,
+                        &engine_settings.path,
+                        "Path to a UCI engine, like stockfish",
+                    ));
+
+                    for (action, label, menu_text) in [
+                        (
+                            MenuAction::EngineSkill,
+                            engine_skill_label(engine_settings.skill),
+                            MenuText::EngineSkill,
+                        ),
+                        (
+                            MenuAction::EngineThinkTime,
+                            engine_think_time_label(engine_settings.think_time),
+                            MenuText::EngineThinkTime,
+                        ),
+                        (
+                            MenuAction::Color,
+                            color_label(host_settings.color),
+                            MenuText::Color,
+                        ),
+                        (
+                            MenuAction::TimeControl,
+                            time_control_label(host_settings.time_control),
+                            MenuText::TimeControl,
+                        ),
+                    ] {
+                        parent
+                            .spawn((button_bundle.clone(), action))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    TextBundle::from_section(label, TextStyle { ..default() }),
+                                    menu_text,
+                                ));
+                            });
+                    }
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 16.0,
+                                color: Color::srgb_u8(255, 120, 120),
+                                ..default()
+                            },
+                        ),
+                        MenuText::EngineError,
+                    ));
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        for (action, label) in
+                            [(MenuAction::Back, "Back"), (MenuAction::PlayEngine, "Play")]
+                        {
+                            parent
+                                .spawn((
+                                    {
+                                        let mut bundle = button_bundle.clone();
+                                        bundle.style.width = Val::Px(160.0);
+                                        bundle
+                                    },
+                                    action,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        label,
+                                        TextStyle { ..default() },
+                                    ));
+                                });
+                        }
+                    });
+                });
+
+            // built-in computer panel, it also plays the other side with the host settings
+            parent
+                .spawn((
+                    {
+                        let mut panel = panel_bundle.clone();
+                        panel.style.display = Display::None;
+                        panel
+                    },
+                    MenuPanel::Computer,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Computer",
+                        TextStyle {
+                            font_size: 42.0,
+                            ..default()
+                        },
+                    ));
+
+                    for (action, label, menu_text) in [
+                        (
+                            MenuAction::Difficulty,
+                            difficulty_label(engine_settings.difficulty),
+                            MenuText::Difficulty,
+                        ),
+                        (
+                            MenuAction::Color,
+                            color_label(host_settings.color),
+                            MenuText::Color,
+                        ),
+                        (
+                            MenuAction::TimeControl,
+                            time_control_label(host_settings.time_control),
+                            MenuText::TimeControl,
+                        ),
+                    ] {
+                        parent
+                            .spawn((button_bundle.clone(), action))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    TextBundle::from_section(label, TextStyle { ..default() }),
+                                    menu_text,
+                                ));
+                            });
+                    }
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        for (action, label) in [
+                            (MenuAction::Back, "Back"),
+                            (MenuAction::PlayComputer, "Play"),
+                        ] {
+                            parent
+                                .spawn((
+                                    {
+                                        let mut bundle = button_bundle.clone();
+                                        bundle.style.width = Val::Px(160.0);
+                                        bundle
+                                    },
+                                    action,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        label,
+                                        TextStyle { ..default() },
+                                    ));
+                                });
+                        }
+                    });
+                });
+
+            // local game panel, the game starts from the host settings' position
+            parent
+                .spawn((
+                    {
+                        let mut panel = panel_bundle.clone();
+                        panel.style.display = Display::None;
+                        panel
+                    },
+                    MenuPanel::Local,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Local game",
+                        TextStyle {
+                            font_size: 42.0,
+                            ..default()
+                        },
+                    ));
+
+                    for (action, label, menu_text) in [
+                        (
+                            MenuAction::TimeControl,
+                            time_control_label(host_settings.time_control),
+                            MenuText::TimeControl,
+                        ),
+                        (
+                            MenuAction::RotateCamera,
+                            rotate_camera_label(local_settings.rotate_camera),
+                            MenuText::RotateCamera,
+                        ),
+                    ] {
+                        parent
+                            .spawn((button_bundle.clone(), action))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    TextBundle::from_section(label, TextStyle { ..default() }),
+                                    menu_text,
+                                ));
+                            });
+                    }
+
+                    parent.spawn(row_bundle.clone()).with_children(|parent| {
+                        for (action, label) in
+                            [(MenuAction::Back, "Back"), (MenuAction::PlayLocal, "Play")]
+                        {
+                            parent
+                                .spawn((
+                                    {
+                                        let mut bundle = button_bundle.clone();
+                                        bundle.style.width = Val::Px(160.0);
+                                        bundle
+                                    },
+                                    action,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        label,
+                                        TextStyle { ..default() },
+                                    ));
+                                });
+                        }
+                    });
                 });
         });
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn menu_update(
     mut game_state: ResMut<NextState<GameState>>,
     mut button_query: Query<
         (&MenuAction, &Interaction, &mut BackgroundColor),
         (Changed<Interaction>, With<Button>),
     >,
-    join_address_query: Query<&TextInputValue>,
+    input_query: Query<(&TextInputValue, &MenuInput)>,
+    mut text_query: Query<(&mut Text, &MenuText)>,
+    mut panel_query: Query<(&mut Style, &MenuPanel)>,
     mut commands: Commands,
     sound_effects: Res<SoundEffects>,
     mut network_handler: ResMut<NetworkHandler>,
+    mut host_settings: ResMut<HostSettings>,
+    mut engine_settings: ResMut<EngineSettings>,
+    mut local_settings: ResMut<LocalSettings>,
+    lobby_browser: Option<Res<LobbyBrowser>>,
 ) {
+    let input_value = |input: MenuInput| -> String {
+        input_query
+            .iter()
+            .find(|(_, i)| **i == input)
+            .map(|(value, _)| value.0.trim().to_string())
+            .unwrap_or_default()
+    };
+    let password =
+        || Some(input_value(MenuInput::Password)).filter(|password| !password.is_empty());
+
     for (action, interaction, mut background_color) in &mut button_query {
         match *interaction {
             Interaction::Pressed => {
                 match *action {
-                    MenuAction::Host => {
-                        println!("Hosting");
-                        game_state.set(GameState::InGame);
+                    MenuAction::Host
+                    | MenuAction::Lobby
+                    | MenuAction::Engine
+                    | MenuAction::Computer
+                    | MenuAction::Local
+                    | MenuAction::Back => {
+                        let shown = match *action {
+                            MenuAction::Host => MenuPanel::Host,
+                            MenuAction::Lobby => MenuPanel::Lobby,
+                            MenuAction::Engine => MenuPanel::Engine,
+                            MenuAction::Computer => MenuPanel::Computer,
+                            MenuAction::Local => MenuPanel::Local,
+                            _ => MenuPanel::Main,
+                        };
+
+                        if *action == MenuAction::Lobby {
+                            let address = input_value(MenuInput::LobbyAddress);
+                            commands.insert_resource(LobbyBrowser::new(address));
+                        } else {
+                            commands.remove_resource::<LobbyBrowser>();
+                        }
+
+                        for (mut style, panel) in panel_query.iter_mut() {
+                            style.display = if *panel == shown {
+                                Display::Flex
+                            } else {
+                                Display::None
+                            };
+                        }
+                    }
+                    MenuAction::StartHosting => {
+                        let fen = input_value(MenuInput::Fen);
+                        let bind_address = parse_bind_address(
+                            &input_value(MenuInput::Interface),
+                            &input_value(MenuInput::Port),
+                        );
+
+                        let error = match &bind_address {
+                            Err(e) => Some(e.clone()),
+                            Ok(_) if !fen.is_empty() && Position::from_fen(&fen).is_err() => {
+                                Some("Invalid FEN string".to_string())
+                            }
+                            Ok(_) => None,
+                        };
+
+                        if let Some(error) = error {
+                            for (mut text, menu_text) in text_query.iter_mut() {
+                                if *menu_text == MenuText::HostError {
+                                    text.sections[0].value = error.clone();
+                                }
+                            }
+                        } else if let Ok(bind_address) = bind_address {
+                            println!("Hosting on {}", bind_address);
+                            host_settings.bind_address = bind_address;
+                            host_settings.fen = Some(fen).filter(|fen| !fen.is_empty());
+                            network_handler.player_name = input_value(MenuInput::Name);
+                            network_handler.role = NetworkRole::Server;
+                            network_handler.spectating = false;
+                            network_handler.lobby_address = None;
+                            network_handler.password = password();
+                            game_state.set(GameState::Connecting);
+                        }
+                    }
+                    MenuAction::ConnectLobby => {
+                        let address = input_value(MenuInput::LobbyAddress);
+                        commands.insert_resource(LobbyBrowser::new(address));
+                    }
+                    MenuAction::HostInLobby | MenuAction::JoinLobbyGame(_) => {
+                        let Some(lobby_browser) = &lobby_browser else {
+                            continue;
+                        };
+
+                        let (role, game) = match *action {
+                            MenuAction::JoinLobbyGame(game) => (NetworkRole::Client, Some(game)),
+                            _ => (NetworkRole::Server, None),
+                        };
+
+                        println!("Playing through the lobby at {}", lobby_browser.address);
+                        host_settings.fen = None;
+                        network_handler.player_name = input_value(MenuInput::Name);
+                        network_handler.role = role;
+                        network_handler.spectating = false;
+                        network_handler.lobby_address = Some(lobby_browser.address.clone());
+                        network_handler.lobby_game = game;
+                        game_state.set(GameState::Connecting);
+                    }
+                    MenuAction::Join | MenuAction::Spectate | MenuAction::JoinDiscovered(_) => {
+                        let address = match *action {
+                            MenuAction::JoinDiscovered(address) => address.to_string(),
+                            _ => input_value(MenuInput::JoinAddress),
+                        };
+                        let spectating = *action == MenuAction::Spectate;
+
+                        if spectating {
+                            println!("Spectating {}", address);
+                        } else {
+                            println!("Joining {}", address);
+                        }
+                        game_state.set(GameState::Connecting);
+                        network_handler.player_name = input_value(MenuInput::Name);
+                        network_handler.role = NetworkRole::Client;
+                        network_handler.address_to_join = Some(address);
+                        network_handler.spectating = spectating;
+                        network_handler.lobby_address = None;
+                        // spectators only watch, the game's password isn't asked from them
+                        network_handler.password = password().filter(|_| !spectating);
+                    }
+                    MenuAction::SelfPlay => {
+                        // we host with the current settings, the other end of the loopback
+                        // plays the client side of the same start packet
+                        let name = input_value(MenuInput::Name);
+                        let start = host_settings.start_packet(&name);
+                        let other_side =
+                            match ClientGameState::from_start(&start, NetworkRole::Client) {
+                                Ok(other_side) => other_side,
+                                Err(e) => {
+                                    println!("Failed to start the game: {}", e);
+                                    continue;
+                                }
+                            };
+                        let (connection, other_connection) = Loopback::pair();
+
+                        commands.insert_resource(SelfPlay {
+                            game_state: other_side,
+                            connection: Box::new(other_connection),
+                        });
+
+                        network_handler.connection = Some(Box::new(connection));
+                        network_handler.start = Some(start);
+                        network_handler.opponent_name = Some("yourself".to_string());
+                        network_handler.player_name = name;
                         network_handler.role = NetworkRole::Server;
+                        network_handler.spectating = false;
+                        network_handler.lobby_address = None;
+                        game_state.set(GameState::InGame);
+                    }
+                    MenuAction::PlayEngine | MenuAction::PlayComputer => {
+                        // we host with the current settings, the engine plays the client side
+                        // over a loopback
+                        let name = input_value(MenuInput::Name);
+                        let start = host_settings.start_packet(&name);
+                        let (connection, engine_connection) = Loopback::pair();
+                        let engine_connection = Box::new(engine_connection);
+
+                        let engine = if *action == MenuAction::PlayComputer {
+                            let computer = Computer::new(engine_settings.difficulty);
+                            let engine = EngineOpponent::start(
+                                computer,
+                                COMPUTER_NAME,
+                                &start,
+                                engine_connection,
+                            );
+                            Ok((engine, COMPUTER_NAME.to_string()))
+                        } else {
+                            engine_settings.path = input_value(MenuInput::EnginePath);
+
+                            if engine_settings.path.is_empty() {
+                                Err("Enter the path to an engine".to_string())
+                            } else {
+                                EngineOpponent::start_uci(
+                                    &engine_settings,
+                                    &start,
+                                    engine_connection,
+                                )
+                                .map(|engine| (engine, engine_name(&engine_settings.path)))
+                                .map_err(|e| format!("Failed to start the engine: {}", e))
+                            }
+                        };
+
+                        match engine {
+                            Ok((engine, engine_name)) => {
+                                println!("Playing against {}", engine_name);
+                                commands.insert_resource(engine);
+
+                                network_handler.connection = Some(Box::new(connection));
+                                network_handler.start = Some(start);
+                                network_handler.opponent_name = Some(engine_name);
+                                network_handler.player_name = name;
+                                network_handler.role = NetworkRole::Server;
+                                network_handler.spectating = false;
+                                network_handler.lobby_address = None;
+                                game_state.set(GameState::InGame);
+                            }
+                            Err(error) => {
+                                for (mut text, menu_text) in text_query.iter_mut() {
+                                    if *menu_text == MenuText::EngineError {
+                                        text.sections[0].value = error.clone();
+                                    }
+                                }
+                            }
+                        }
                     }
-                    MenuAction::Join => {
-                        let join_address_element = join_address_query
+                    MenuAction::EngineSkill => {
+                        let index = ENGINE_SKILLS
                             .iter()
-                            .next()
-                            .expect("No join address element");
+                            .position(|skill| *skill == engine_settings.skill)
+                            .unwrap_or(0);
+                        engine_settings.skill = ENGINE_SKILLS[(index + 1) % ENGINE_SKILLS.len()];
+                    }
+                    MenuAction::PlayLocal => {
+                        println!("Starting a local game");
+                        commands.insert_resource(HotSeat {
+                            start: host_settings.start_packet(&input_value(MenuInput::Name)),
+                            rotate_camera: local_settings.rotate_camera,
+                            camera_angle: 0.0,
+                        });
 
-                        println!("Joining {}", join_address_element.0);
+                        // nothing else of the network handler is looked at without a connection
+                        network_handler.spectating = false;
                         game_state.set(GameState::InGame);
-                        network_handler.role = NetworkRole::Client;
-                        network_handler.address_to_join = Some(join_address_element.0.clone());
+                    }
+                    MenuAction::RotateCamera => {
+                        local_settings.rotate_camera = !local_settings.rotate_camera;
+                    }
+                    MenuAction::Difficulty => {
+                        let index = DIFFICULTIES
+                            .iter()
+                            .position(|difficulty| *difficulty == engine_settings.difficulty)
+                            .unwrap_or(0);
+                        engine_settings.difficulty = DIFFICULTIES[(index + 1) % DIFFICULTIES.len()];
+                    }
+                    MenuAction::EngineThinkTime => {
+                        let index = ENGINE_THINK_TIMES
+                            .iter()
+                            .position(|think_time| *think_time == engine_settings.think_time)
+                            .unwrap_or(0);
+                        engine_settings.think_time =
+                            ENGINE_THINK_TIMES[(index + 1) % ENGINE_THINK_TIMES.len()];
+                    }
+                    MenuAction::TimeControl => {
+                        let index = TIME_CONTROLS
+                            .iter()
+                            .position(|time_control| *time_control == host_settings.time_control)
+                            .unwrap_or(0);
+                        host_settings.time_control =
+                            TIME_CONTROLS[(index + 1) % TIME_CONTROLS.len()];
+                    }
+                    MenuAction::RecordTraffic => {
+                        network_handler.record_traffic = !network_handler.record_traffic;
+                    }
+                    MenuAction::Color => {
+                        host_settings.color = match host_settings.color {
+                            HostColor::White => HostColor::Black,
+                            HostColor::Black => HostColor::Random,
+                            HostColor::Random => HostColor::White,
+                        };
+                    }
+                }
+
+                for (mut text, menu_text) in text_query.iter_mut() {
+                    match menu_text {
+                        MenuText::TimeControl => {
+                            text.sections[0].value = time_control_label(host_settings.time_control)
+                        }
+                        MenuText::Color => {
+                            text.sections[0].value = color_label(host_settings.color)
+                        }
+                        MenuText::RecordTraffic => {
+                            text.sections[0].value =
+                                record_traffic_label(network_handler.record_traffic)
+                        }
+                        MenuText::EngineSkill => {
+                            text.sections[0].value = engine_skill_label(engine_settings.skill)
+                        }
+                        MenuText::EngineThinkTime => {
+                            text.sections[0].value =
+                                engine_think_time_label(engine_settings.think_time)
+                        }
+                        MenuText::RotateCamera => {
+                            text.sections[0].value =
+                                rotate_camera_label(local_settings.rotate_camera)
+                        }
+                        MenuText::Difficulty => {
+                            text.sections[0].value = difficulty_label(engine_settings.difficulty)
+                        }
+                        MenuText::HostError | MenuText::EngineError => {}
                     }
                 }
 
@@ -195,3 +1289,270 @@ pub(crate) fn menu_update(
         }
     }
 }
+
+/// Collects announcements from hosts on the local network and keeps the list of join buttons
+/// up to date
+pub(crate) fn discover_games(
+    mut commands: Commands,
+    mut lan_games: ResMut<LanGames>,
+    list_query: Query<Entity, With<GameList>>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    let lan_games = &mut *lan_games;
+
+    if let Ok(listener) = &lan_games.listener {
+        for (address, announcement) in listener.receive() {
+            match lan_games
+                .games
+                .iter_mut()
+                .find(|game| game.address == address)
+            {
+                Some(game) => {
+                    if game.announcement != announcement {
+                        game.announcement = announcement;
+                        lan_games.dirty = true;
+                    }
+                    game.last_seen = now;
+                }
+                None => {
+                    lan_games.games.push(LanGame {
+                        address,
+                        announcement,
+                        last_seen: now,
+                    });
+                    lan_games.dirty = true;
+                }
+            }
+        }
+    }
+
+    // hosts stop announcing once their game starts or they cancel
+    let count = lan_games.games.len();
+    lan_games
+        .games
+        .retain(|game| now - game.last_seen < ANNOUNCE_TIMEOUT);
+    if lan_games.games.len() != count {
+        lan_games.dirty = true;
+    }
+
+    if !lan_games.dirty {
+        return;
+    }
+    lan_games.dirty = false;
+
+    for list in &list_query {
+        commands.entity(list).despawn_descendants();
+        commands.entity(list).with_children(|parent| {
+            let status = match &lan_games.listener {
+                Err(e) => format!("Can't search for local games: {}", e),
+                Ok(_) if lan_games.games.is_empty() => {
+                    "Searching for games on your network...".to_string()
+                }
+                Ok(_) => "Games on your network".to_string(),
+            };
+            parent.spawn(TextBundle::from_section(
+                status,
+                TextStyle {
+                    font_size: 16.0,
+                    ..default()
+                },
+            ));
+
+            for game in &lan_games.games {
+                let announcement = &game.announcement;
+                let color = match announcement.host_color {
+                    HostColor::White => "black",
+                    HostColor::Black => "white",
+                    HostColor::Random => "random color",
+                };
+                let time_control = match announcement.time_control {
+                    Some(time_control) => time_control.to_string(),
+                    None => "no clock".to_string(),
+                };
+
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::all(Val::Px(8.0)),
+                                display: Display::Flex,
+                                flex_direction: FlexDirection::Column,
+                                ..default()
+                            },
+                            border_radius: BorderRadius::all(Val::Px(6.0)),
+                            background_color: BUTTON_COLOR.into(),
+                            ..default()
+                        },
+                        MenuAction::JoinDiscovered(game.address),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            &announcement.name,
+                            TextStyle { ..default() },
+                        ));
+                        parent.spawn(TextBundle::from_section(
+                            format!("{}, you play {}, {}", time_control, color, game.address),
+                            TextStyle {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+/// Asks the lobby for its games every few seconds and keeps the list of join buttons up to date
+pub(crate) fn browse_lobby(
+    mut commands: Commands,
+    lobby_browser: Option<ResMut<LobbyBrowser>>,
+    list_query: Query<Entity, With<LobbyGameList>>,
+    time: Res<Time>,
+) {
+    let Some(mut lobby_browser) = lobby_browser else {
+        return;
+    };
+    let now = time.elapsed_seconds();
+    let lobby_browser = &mut *lobby_browser;
+
+    if let Some(refresh) = lobby_browser.refresh.as_ref() {
+        if let Some(result) = refresh.poll() {
+            match result {
+                Ok(Some(games)) => lobby_browser.games = Some(Ok(games)),
+                Err(e) => lobby_browser.games = Some(Err(e.to_string())),
+                Ok(None) => {}
+            }
+            lobby_browser.refresh = None;
+            lobby_browser.dirty = true;
+        }
+    } else if lobby_browser
+        .last_refresh
+        .map_or(true, |last_refresh| now - last_refresh >= LOBBY_REFRESH)
+    {
+        let address = lobby_browser.address.clone();
+        lobby_browser.refresh = Some(ConnectThread::spawn(move |cancel| {
+            lobby::list_games(&address, cancel)
+        }));
+        lobby_browser.last_refresh = Some(now);
+    }
+
+    if !lobby_browser.dirty {
+        return;
+    }
+    lobby_browser.dirty = false;
+
+    for list in &list_query {
+        commands.entity(list).despawn_descendants();
+        commands.entity(list).with_children(|parent| {
+            let status = match &lobby_browser.games {
+                None => "Connecting to the lobby...".to_string(),
+                Some(Err(e)) => e.clone(),
+                Some(Ok(games)) if games.is_empty() => "No open games in the lobby".to_string(),
+                Some(Ok(_)) => "Open games".to_string(),
+            };
+            parent.spawn(TextBundle::from_section(
+                status,
+                TextStyle {
+                    font_size: 16.0,
+                    ..default()
+                },
+            ));
+
+            for game in lobby_browser.games.iter().flatten().flatten() {
+                let color = match game.host_is_white {
+                    Some(true) => "black",
+                    Some(false) => "white",
+                    None => "random color",
+                };
+                let time_control = match game.time {
+                    Some(time) => TimeControl {
+                        time,
+                        inc: game.inc.unwrap_or(0),
+                    }
+                    .to_string(),
+                    None => "no clock".to_string(),
+                };
+
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::all(Val::Px(8.0)),
+                                display: Display::Flex,
+                                flex_direction: FlexDirection::Column,
+                                ..default()
+                            },
+                            border_radius: BorderRadius::all(Val::Px(6.0)),
+                            background_color: BUTTON_COLOR.into(),
+                            ..default()
+                        },
+                        MenuAction::JoinLobbyGame(game.id),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            &game.host,
+                            TextStyle { ..default() },
+                        ));
+                        parent.spawn(TextBundle::from_section(
+                            format!("{}, you play {}", time_control, color),
+                            TextStyle {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+pub(crate) fn menu_cleanup(mut commands: Commands) {
+    commands.remove_resource::<LanGames>();
+    commands.remove_resource::<LobbyBrowser>();
+}
+
+/// Checks the interface and port typed in the host panel, and that we are allowed to listen
+/// there, so mistakes show up in the menu instead of after leaving it
+fn parse_bind_address(interface: &str, port: &str) -> Result<SocketAddr, String> {
+    let interface = if interface.is_empty() {
+        "0.0.0.0"
+    } else {
+        interface.trim_start_matches('[').trim_end_matches(']')
+    };
+
+    let port = if port.is_empty() {
+        DEFAULT_PORT
+    } else {
+        port.parse::<u16>()
+            .map_err(|_| format!("Invalid port {}", port))?
+    };
+
+    let address = (interface, port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or(format!("Invalid interface {}", interface))?;
+
+    // the listener is dropped right away, the connecting screen binds again
+    TcpListener::bind(address).map_err(|e| format!("Can't listen on {}: {}", address, e))?;
+
+    Ok(address)
+}
+
+/// Only the text field that was clicked last receives keyboard input
+pub(crate) fn focus_text_input(
+    interaction_query: Query<(Entity, &Interaction), (Changed<Interaction>, With<MenuInput>)>,
+    mut input_query: Query<(Entity, &mut TextInputInactive)>,
+) {
+    for (clicked, interaction) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        for (entity, mut inactive) in input_query.iter_mut() {
+            inactive.0 = entity != clicked;
+        }
+    }
+}
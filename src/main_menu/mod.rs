@@ -11,10 +11,16 @@ pub fn menu_plugin(app: &mut App) {
     app.add_systems(OnEnter(GameState::MainMenu), main_menu::menu_setup)
         .add_systems(
             Update,
-            main_menu::menu_update.run_if(in_state(GameState::MainMenu)),
+            (
+                main_menu::menu_update,
+                main_menu::focus_text_input,
+                main_menu::discover_games,
+                main_menu::browse_lobby,
+            )
+                .run_if(in_state(GameState::MainMenu)),
         )
         .add_systems(
             OnExit(GameState::MainMenu),
-            despawn_screen::<OnMainMenuScreen>,
+            (despawn_screen::<OnMainMenuScreen>, main_menu::menu_cleanup),
         );
 }
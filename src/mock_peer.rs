@@ -0,0 +1,501 @@
+//! A fake opponent that plays a fixed script of packets instead of thinking. It's used by the
+//! `mock_peer` binary to poke at a running game, and by the tests to check the game's side of
+//! the protocol without a window.
+//!
+//! A script has one step per line, `#` starts a comment:
+//!
+//! ```text
+//! send start <white|black> [time=<secs>] [inc=<secs>] [name=<name>] [fen=<rest of line>]
+//! send move <from> <to> [promote=<q|r|b|n>] [draw]
+//! send forfeit
+//! send ack <ok|nack> [end=<checkmate|draw>]
+//! expect start [white|black]
+//! expect move [<from> <to>] [promote=<q|r|b|n>] [draw]
+//! expect forfeit
+//! expect ack [ok|nack] [end=<checkmate|draw|none>]
+//! expect any
+//! wait <ms>
+//! close
+//! ```
+//!
+//! Squares are written like `e2`. `white`/`black` in a `Start` is `is_white`, which is always
+//! from the server's point of view.
+//!
+//! Captures recorded by the game can be replayed too, the mock then sends what the opponent sent
+//! with the original timing and expects exactly what the game sent.
+
+use std::{
+    fmt,
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+use chess_networking::PromotionPiece;
+
+use crate::{
+    game::{
+        capture::CaptureFile,
+        networking::{Connection, Message, NetworkError, Transport},
+    },
+    general::resources::NetworkRole,
+};
+
+/// How long an `expect` step waits before the script fails
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum MockError {
+    /// The script has a line that isn't a step
+    Parse { line: usize, message: String },
+    /// Couldn't connect, or the connection broke
+    Network(String),
+    /// A packet arrived that the script didn't expect
+    Unexpected {
+        line: usize,
+        expected: String,
+        received: String,
+    },
+    /// Nothing arrived before the timeout
+    Timeout { line: usize, expected: String },
+    /// The capture file is broken
+    Capture(String),
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MockError::Network(e) => write!(f, "{}", e),
+            MockError::Unexpected {
+                line,
+                expected,
+                received,
+            } => write!(
+                f,
+                "line {}: expected {}, received {}",
+                line, expected, received
+            ),
+            MockError::Timeout { line, expected } => {
+                write!(f, "line {}: timed out waiting for {}", line, expected)
+            }
+            MockError::Capture(e) => write!(f, "bad capture, {}", e),
+        }
+    }
+}
+
+impl From<NetworkError> for MockError {
+    fn from(e: NetworkError) -> Self {
+        MockError::Network(e.to_string())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum EndState {
+    CheckMate,
+    Draw,
+}
+
+/// A packet the script sends, promotions are written as in the script (q, r, b or n)
+#[derive(Debug)]
+enum Outgoing {
+    /// A packet taken from a capture
+    Captured(Message),
+    Start(chess_networking::Start),
+    Move {
+        from: (u8, u8),
+        to: (u8, u8),
+        promotion: Option<char>,
+        forfeit: bool,
+        offer_draw: bool,
+    },
+    Ack {
+        ok: bool,
+        end_state: Option<EndState>,
+    },
+}
+
+/// What an `expect` step accepts, `None` fields match anything
+#[derive(Debug)]
+enum Expected {
+    Any,
+    /// Exactly this packet, used when replaying captures
+    Captured(Message),
+    Start {
+        is_white: Option<bool>,
+    },
+    Move {
+        squares: Option<((u8, u8), (u8, u8))>,
+        promotion: Option<char>,
+        offer_draw: bool,
+    },
+    Forfeit,
+    Ack {
+        ok: Option<bool>,
+        end_state: Option<Option<EndState>>,
+    },
+}
+
+#[derive(Debug)]
+enum Step {
+    Send(Outgoing),
+    Expect(Expected),
+    Wait(Duration),
+    Close,
+}
+
+#[derive(Debug)]
+pub struct Script {
+    /// Steps with the line they came from, for error messages
+    steps: Vec<(usize, Step)>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, MockError> {
+        let mut steps = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let step = parse_step(line).map_err(|message| MockError::Parse {
+                line: index + 1,
+                message,
+            })?;
+            steps.push((index + 1, step));
+        }
+
+        Ok(Script { steps })
+    }
+
+    /// Turns a capture into a script for the opponent of whoever recorded it, returns the role
+    /// the opponent had
+    pub(crate) fn from_capture(source: &str) -> Result<(Script, NetworkRole), MockError> {
+        let capture = CaptureFile::parse(source).map_err(MockError::Capture)?;
+        let mut steps = Vec::new();
+        let mut last_millis = 0;
+
+        for packet in &capture.packets {
+            let message = Message::decode(&packet.bytes).ok_or_else(|| {
+                MockError::Capture(format!("line {}: not a valid packet", packet.line))
+            })?;
+
+            if packet.sent {
+                steps.push((packet.line, Step::Expect(Expected::Captured(message))));
+            } else {
+                let gap = packet.millis.saturating_sub(last_millis);
+                if gap > 0 {
+                    steps.push((packet.line, Step::Wait(Duration::from_millis(gap))));
+                }
+                steps.push((packet.line, Step::Send(Outgoing::Captured(message))));
+            }
+
+            last_millis = packet.millis;
+        }
+
+        let role = match capture.role {
+            NetworkRole::Server => NetworkRole::Client,
+            NetworkRole::Client => NetworkRole::Server,
+        };
+
+        Ok((Script { steps }, role))
+    }
+
+    /// Plays the opponent from a capture against the game, hosting on `address` if the
+    /// opponent was the host and joining `address` otherwise
+    pub fn replay(source: &str, address: &str) -> Result<(), MockError> {
+        let (script, role) = Script::from_capture(source)?;
+
+        match role {
+            NetworkRole::Server => script.serve(address),
+            NetworkRole::Client => script.connect(address),
+        }
+    }
+
+    /// Waits for the game to connect to `address` and plays the script
+    pub fn serve(&self, address: &str) -> Result<(), MockError> {
+        let mut connection = Connection::new_server(address, &AtomicBool::new(false))?
+            .ok_or_else(|| MockError::Network("Stopped listening".to_string()))?;
+        self.run(&mut connection)
+    }
+
+    /// Connects to a game hosted on `address` and plays the script
+    pub fn connect(&self, address: &str) -> Result<(), MockError> {
+        let mut connection = Connection::new_client(address)?;
+        self.run(&mut connection)
+    }
+
+    /// Plays the script over `connection`. A `close` step ends the script early, the caller
+    /// closes the connection by dropping it.
+    pub(crate) fn run(&self, connection: &mut dyn Transport) -> Result<(), MockError> {
+        for (line, step) in &self.steps {
+            match step {
+                Step::Send(outgoing) => {
+                    println!("-> {:?}", outgoing);
+                    connection.write(outgoing.to_message())?;
+                }
+                Step::Expect(expected) => {
+                    let message =
+                        read_with_timeout(connection, EXPECT_TIMEOUT)?.ok_or_else(|| {
+                            MockError::Timeout {
+                                line: *line,
+                                expected: format!("{:?}", expected),
+                            }
+                        })?;
+                    println!("<- {:?}", message);
+
+                    if !expected.matches(&message) {
+                        return Err(MockError::Unexpected {
+                            line: *line,
+                            expected: format!("{:?}", expected),
+                            received: format!("{:?}", message),
+                        });
+                    }
+                }
+                Step::Wait(duration) => std::thread::sleep(*duration),
+                Step::Close => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A readable listing of a capture file
+pub fn decode_capture(source: &str) -> Result<String, MockError> {
+    CaptureFile::parse(source)
+        .map(|capture| capture.pretty_print())
+        .map_err(MockError::Capture)
+}
+
+fn read_with_timeout(
+    connection: &mut dyn Transport,
+    timeout: Duration,
+) -> Result<Option<Message>, NetworkError> {
+    let deadline = Instant::now() + timeout;
+
+    while Instant::now() < deadline {
+        if let Some(message) = connection.read()? {
+            return Ok(Some(message));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(None)
+}
+
+impl Outgoing {
+    fn to_message(&self) -> Message {
+        match *self {
+            Outgoing::Captured(ref message) => message.clone(),
+            Outgoing::Start(ref packet) => Message::Start(packet.clone(), Vec::new()),
+            Outgoing::Move {
+                from,
+                to,
+                promotion,
+                forfeit,
+                offer_draw,
+            } => Message::Move(chess_networking::Move {
+                from,
+                to,
+                promotion: promotion.and_then(promotion_piece),
+                forfeit,
+                offer_draw,
+            }),
+            Outgoing::Ack { ok, end_state } => Message::Ack(chess_networking::Ack {
+                ok,
+                end_state: end_state.map(|end_state| match end_state {
+                    EndState::CheckMate => chess_networking::GameState::CheckMate,
+                    EndState::Draw => chess_networking::GameState::Draw,
+                }),
+            }),
+        }
+    }
+}
+
+impl Expected {
+    fn matches(&self, message: &Message) -> bool {
+        match (self, message) {
+            (Expected::Any, _) => true,
+            (Expected::Captured(expected), message) => {
+                expected.clone().encode().ok() == message.clone().encode().ok()
+            }
+            (Expected::Start { is_white }, Message::Start(packet, _)) => {
+                is_white.map_or(true, |is_white| packet.is_white == is_white)
+            }
+            (Expected::Forfeit, Message::Move(packet)) => packet.forfeit,
+            (
+                Expected::Move {
+                    squares,
+                    promotion,
+                    offer_draw,
+                },
+                Message::Move(packet),
+            ) => {
+                !packet.forfeit
+                    && squares.map_or(true, |squares| squares == (packet.from, packet.to))
+                    && promotion.map_or(true, |promotion| {
+                        packet.promotion.as_ref().map(promotion_name) == Some(promotion)
+                    })
+                    && packet.offer_draw == *offer_draw
+            }
+            (Expected::Ack { ok, end_state }, Message::Ack(packet)) => {
+                let received_end_state =
+                    packet.end_state.as_ref().map(|end_state| match end_state {
+                        chess_networking::GameState::CheckMate => EndState::CheckMate,
+                        chess_networking::GameState::Draw => EndState::Draw,
+                    });
+
+                ok.map_or(true, |ok| packet.ok == ok)
+                    && end_state.map_or(true, |end_state| received_end_state == end_state)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn promotion_name(piece: &PromotionPiece) -> char {
+    match piece {
+        PromotionPiece::Queen => 'q',
+        PromotionPiece::Rook => 'r',
+        PromotionPiece::Bishop => 'b',
+        PromotionPiece::Knight => 'n',
+    }
+}
+
+fn promotion_piece(name: char) -> Option<PromotionPiece> {
+    match name {
+        'q' => Some(PromotionPiece::Queen),
+        'r' => Some(PromotionPiece::Rook),
+        'b' => Some(PromotionPiece::Bishop),
+        'n' => Some(PromotionPiece::Knight),
+        _ => None,
+    }
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    // fen strings contain spaces so they take the rest of the line
+    let (line, fen) = match line.split_once("fen=") {
+        Some((line, fen)) => (line, Some(fen.trim().to_string())),
+        None => (line, None),
+    };
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let option = |key: &str| option(&words, key);
+    let flag = |name: &str| words.contains(&name);
+    let number = |key: &str| -> Result<Option<u64>, String> {
+        option(key)
+            .map(|value| value.parse().map_err(|_| format!("bad number for {}", key)))
+            .transpose()
+    };
+
+    let step = match words.as_slice() {
+        ["send", "start", ..] => Step::Send(Outgoing::Start(chess_networking::Start {
+            is_white: flag("white") || !flag("black"),
+            name: option("name").map(|name| name.to_string()),
+            fen,
+            time: number("time")?,
+            inc: number("inc")?,
+        })),
+        ["send", "move", from, to, ..] => Step::Send(Outgoing::Move {
+            from: parse_square(from)?,
+            to: parse_square(to)?,
+            promotion: option("promote").map(parse_promotion).transpose()?,
+            forfeit: false,
+            offer_draw: flag("draw"),
+        }),
+        ["send", "forfeit"] => Step::Send(Outgoing::Move {
+            from: (0, 0),
+            to: (0, 0),
+            promotion: None,
+            forfeit: true,
+            offer_draw: false,
+        }),
+        ["send", "ack", ok, ..] => Step::Send(Outgoing::Ack {
+            ok: parse_ok(ok)?,
+            end_state: match option("end") {
+                Some(end_state) => parse_end_state(end_state)?,
+                None => None,
+            },
+        }),
+        ["expect", "any"] => Step::Expect(Expected::Any),
+        ["expect", "start", ..] => Step::Expect(Expected::Start {
+            is_white: if flag("white") {
+                Some(true)
+            } else if flag("black") {
+                Some(false)
+            } else {
+                None
+            },
+        }),
+        ["expect", "forfeit"] => Step::Expect(Expected::Forfeit),
+        ["expect", "move", rest @ ..] => {
+            let squares = match rest {
+                [from, to, ..] if !from.contains('=') && *from != "draw" => {
+                    Some((parse_square(from)?, parse_square(to)?))
+                }
+                _ => None,
+            };
+
+            Step::Expect(Expected::Move {
+                squares,
+                promotion: option("promote").map(parse_promotion).transpose()?,
+                offer_draw: flag("draw"),
+            })
+        }
+        ["expect", "ack", rest @ ..] => Step::Expect(Expected::Ack {
+            ok: match rest.first() {
+                Some(ok) if !ok.contains('=') => Some(parse_ok(ok)?),
+                _ => None,
+            },
+            end_state: option("end").map(parse_end_state).transpose()?,
+        }),
+        ["wait", ms] => Step::Wait(Duration::from_millis(
+            ms.parse().map_err(|_| format!("bad wait time {}", ms))?,
+        )),
+        ["close"] => Step::Close,
+        _ => return Err(format!("unknown step \"{}\"", line.trim())),
+    };
+
+    Ok(step)
+}
+
+/// The value of a `key=value` word
+fn option<'a>(words: &[&'a str], key: &str) -> Option<&'a str> {
+    words
+        .iter()
+        .find_map(|word| word.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// "e2" to the (x, y) coordinates used in packets, y = 0 is white's back rank
+fn parse_square(square: &str) -> Result<(u8, u8), String> {
+    match square.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok((file - b'a', rank - b'1')),
+        _ => Err(format!("bad square {}", square)),
+    }
+}
+
+fn parse_promotion(piece: &str) -> Result<char, String> {
+    match piece {
+        "q" | "r" | "b" | "n" => Ok(piece.chars().next().unwrap_or('q')),
+        _ => Err(format!("bad promotion piece {}", piece)),
+    }
+}
+
+fn parse_ok(ok: &str) -> Result<bool, String> {
+    match ok {
+        "ok" => Ok(true),
+        "nack" => Ok(false),
+        _ => Err(format!("expected ok or nack, got {}", ok)),
+    }
+}
+
+fn parse_end_state(end_state: &str) -> Result<Option<EndState>, String> {
+    match end_state {
+        "checkmate" => Ok(Some(EndState::CheckMate)),
+        "draw" => Ok(Some(EndState::Draw)),
+        "none" => Ok(None),
+        _ => Err(format!("bad end state {}", end_state)),
+    }
+}